@@ -0,0 +1,46 @@
+use std::time::Duration;
+use std::time::Instant;
+
+use chat_splitter::CachingSplitter;
+use chat_splitter::ChatSplitter;
+use tiktoken_rs::ChatCompletionRequestMessage;
+
+const MODEL: &str = "gpt-4";
+const MESSAGES: usize = 1000;
+const ROUNDS: u32 = 10;
+
+fn main() {
+    let mut messages: Vec<_> = (0..MESSAGES)
+        .map(|i| ChatCompletionRequestMessage {
+            role: if i % 2 == 0 { "user" } else { "assistant" }.to_owned(),
+            content: Some(format!("Message number {i}. ").repeat(16)),
+            ..Default::default()
+        })
+        .collect();
+
+    let splitter = ChatSplitter::new(MODEL).max_input_tokens(4_096_u16);
+    let elapsed = time_rounds(&mut messages, |messages| {
+        splitter.split(messages);
+    });
+    println!("ChatSplitter: {elapsed:?} per split");
+
+    let mut splitter = CachingSplitter::new(splitter);
+    let elapsed = time_rounds(&mut messages, |messages| {
+        splitter.split(messages);
+    });
+    println!("CachingSplitter: {elapsed:?} per split");
+}
+
+/// Split the messages a few times,
+/// changing only the last one every time.
+fn time_rounds(
+    messages: &mut [ChatCompletionRequestMessage],
+    mut split: impl FnMut(&[ChatCompletionRequestMessage]),
+) -> Duration {
+    let start = Instant::now();
+    for round in 0..ROUNDS {
+        messages.last_mut().unwrap().content = Some(format!("Changed {round} times."));
+        split(messages);
+    }
+    start.elapsed() / ROUNDS
+}
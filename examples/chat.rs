@@ -1,4 +1,5 @@
 use std::error::Error;
+use std::slice;
 
 use async_openai::types::ChatCompletionRequestAssistantMessageArgs;
 use async_openai::types::ChatCompletionRequestMessage;
@@ -26,18 +27,22 @@ async fn main() -> Result<(), Box<dyn Error>> {
     );
     assert!(stored_messages.len() > MAX_MESSAGES);
 
-    let (_outdated_messages, recent_messages) = ChatSplitter::new(MODEL)
+    let system_message: ChatCompletionRequestMessage =
+        ChatCompletionRequestSystemMessageArgs::default()
+            .role(Role::System)
+            .content("You are a helpful assistant.")
+            .build()?
+            .into();
+
+    let recent_messages = ChatSplitter::new(MODEL)
         .max_tokens(MAX_TOKENS)
         .max_messages(MAX_MESSAGES)
-        .split(&stored_messages);
+        .reserve_prefix(slice::from_ref(&system_message))
+        .recent_async_openai(&stored_messages);
 
-    let mut messages = vec![ChatCompletionRequestSystemMessageArgs::default()
-        .role(Role::System)
-        .content("You are a helpful assistant.")
-        .build()?
-        .into()];
-    messages.extend(recent_messages.iter().cloned());
-    assert!(messages.len() <= MAX_MESSAGES + 1);
+    let mut messages = vec![system_message];
+    messages.extend(recent_messages);
+    assert!(messages.len() <= MAX_MESSAGES);
 
     let request = CreateChatCompletionRequestArgs::default()
         .model(MODEL)
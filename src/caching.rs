@@ -0,0 +1,204 @@
+//! Splitting with cached token counts.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::hash::Hasher;
+
+use crate::convert::try_into_tiktoken_rs;
+use crate::ChatSplitter;
+use crate::IntoChatCompletionRequestMessage;
+use crate::SplitError;
+
+/// Chat splitter that caches the tokens each message takes.
+///
+/// Token counts are keyed by a hash of the role,
+/// content,
+/// name
+/// and
+/// function call of each message,
+/// so that repeated splits of a growing or slightly changing chat only
+/// tokenize new or changed messages.
+/// The configuration of the underlying [`ChatSplitter`] cannot be changed,
+/// so cached counts never go stale.
+/// The cache is never evicted on its own,
+/// and takes a few bytes per distinct message ever split:
+/// use [`CachingSplitter::clear`] to free it,
+/// e.g.,
+/// when switching conversations.
+#[derive(Clone, Debug)]
+pub struct CachingSplitter {
+    /// The underlying chat splitter.
+    splitter: ChatSplitter,
+
+    /// The tokens each message takes,
+    /// keyed by the hash of the message.
+    cache: HashMap<u64, usize>,
+}
+
+impl CachingSplitter {
+    /// Create a new [`CachingSplitter`] with an empty cache.
+    #[inline]
+    pub fn new(splitter: ChatSplitter) -> Self {
+        Self {
+            splitter,
+            cache: HashMap::new(),
+        }
+    }
+
+    /// Get the number of cached token counts.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Check whether the cache is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    /// Clear the cache.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+
+    /// Split the chat into two groups of messages,
+    /// the 'outdated' and the
+    /// 'recent' ones.
+    ///
+    /// This is the same as [`CachingSplitter::try_split`],
+    /// but panics instead of returning an error.
+    ///
+    /// # Panics
+    ///
+    /// If [`CachingSplitter::try_split`] fails.
+    #[inline]
+    pub fn split<'a, M>(&mut self, messages: &'a [M]) -> (&'a [M], &'a [M])
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        self.try_split(messages).unwrap()
+    }
+
+    /// Split the chat into two groups of messages,
+    /// the 'outdated' and the
+    /// 'recent' ones.
+    ///
+    /// This is the same as [`ChatSplitter::try_split`],
+    /// but only messages not in the cache are tokenized.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`ChatSplitter::try_split`].
+    #[inline]
+    pub fn try_split<'a, M>(&mut self, messages: &'a [M]) -> Result<(&'a [M], &'a [M]), SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        let tokens = self.message_tokens(messages)?;
+        Ok(self
+            .splitter
+            .plan_with_tokens(messages, tokens.as_deref())?
+            .split(messages))
+    }
+
+    /// Get the tokens each message takes,
+    /// tokenizing only the messages not in the cache,
+    /// or
+    /// `None` if tokens are not considered
+    /// (see [`ChatSplitter::strategy`]).
+    ///
+    /// # Errors
+    ///
+    /// If tokenizer for the specified model is not found or is not a supported
+    /// chat model,
+    /// or if some message cannot be converted for tokenization.
+    #[inline]
+    fn message_tokens<M>(&mut self, messages: &[M]) -> Result<Option<Vec<usize>>, SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        if !self.splitter.strategy.uses_tokens() {
+            return Ok(None);
+        }
+        let converted = try_into_tiktoken_rs(messages)?;
+        let keys: Vec<_> = converted.iter().map(cache_key).collect();
+
+        let (missing_keys, missing): (Vec<_>, Vec<_>) = keys
+            .iter()
+            .zip(&converted)
+            .filter(|(key, _)| !self.cache.contains_key(key))
+            .map(|(key, message)| (key, message.clone()))
+            .unzip();
+        if !missing.is_empty() {
+            let counts = self
+                .splitter
+                .using_tokenizer(|tokenizer| tokenizer.count_message_tokens(&missing))?;
+            self.cache.extend(missing_keys.into_iter().zip(counts));
+        }
+
+        // Coalescing and separators depend on the neighbors of each message,
+        // so they are never cached.
+        let tokens = keys.iter().map(|key| self.cache[key]).collect();
+        let tokens = self.splitter.adjust_message_tokens(&converted, tokens)?;
+        tokens
+            .into_iter()
+            .zip(messages)
+            .enumerate()
+            .map(|(index, (tokens, message))| {
+                Ok(tokens + self.splitter.message_image_tokens(index, message)?)
+            })
+            .collect::<Result<_, _>>()
+            .map(Some)
+    }
+}
+
+/// Get the key of a message in the cache of a [`CachingSplitter`].
+#[inline]
+fn cache_key(message: &tiktoken_rs::ChatCompletionRequestMessage) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    message.role.hash(&mut hasher);
+    message.content.hash(&mut hasher);
+    message.name.hash(&mut hasher);
+    if let Some(function_call) = &message.function_call {
+        function_call.name.hash(&mut hasher);
+        function_call.arguments.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+#[cfg(all(test, feature = "async-openai"))]
+mod tests {
+    use super::*;
+    use crate::tests::conversation;
+    use crate::tests::fixtures;
+    use crate::tests::user_message;
+
+    #[test]
+    fn caching_splitter_matches_chat_splitter() {
+        let mut messages = conversation(8);
+
+        let splitter = ChatSplitter::new("gpt-4").max_input_tokens(100_u16);
+        let mut caching = CachingSplitter::new(splitter.clone());
+        assert!(caching.is_empty());
+
+        assert_eq!(caching.split(&messages), splitter.split(&messages));
+        let cached = caching.len();
+        assert!(cached > 0);
+
+        *messages.last_mut().unwrap() = user_message("Something else entirely.");
+        assert_eq!(caching.split(&messages), splitter.split(&messages));
+        assert_eq!(caching.len(), cached + 1);
+
+        caching.clear();
+        assert!(caching.is_empty());
+
+        for (splitter, messages) in fixtures() {
+            let mut caching = CachingSplitter::new(splitter.clone());
+            assert_eq!(caching.split(&messages), splitter.split(&messages));
+            assert_eq!(caching.split(&messages), splitter.split(&messages));
+        }
+    }
+}
@@ -0,0 +1,1121 @@
+//! Conversions between message types.
+
+use std::hash::Hash;
+
+use crate::ConversionError;
+use crate::SplitError;
+
+/// Convert messages for tokenization.
+///
+/// # Errors
+///
+/// If some message cannot be converted for tokenization.
+#[inline]
+pub(crate) fn try_into_tiktoken_rs<M>(
+    messages: &[M],
+) -> Result<Vec<tiktoken_rs::ChatCompletionRequestMessage>, SplitError>
+where
+    M: IntoChatCompletionRequestMessage + Clone,
+{
+    Ok(try_into_tiktoken_rs_vec(messages.iter().cloned())?)
+}
+
+/// Convert messages to [`tiktoken_rs` chat completion request message
+/// type](`tiktoken_rs::ChatCompletionRequestMessage`).
+///
+/// # Panics
+///
+/// If some message cannot be converted.
+#[inline]
+pub fn into_tiktoken_rs_vec<M>(
+    messages: impl IntoIterator<Item = M>,
+) -> Vec<tiktoken_rs::ChatCompletionRequestMessage>
+where
+    M: IntoChatCompletionRequestMessage,
+{
+    messages
+        .into_iter()
+        .map(IntoChatCompletionRequestMessage::into_tiktoken_rs)
+        .collect()
+}
+
+/// Try to convert messages to [`tiktoken_rs` chat completion request message
+/// type](`tiktoken_rs::ChatCompletionRequestMessage`).
+///
+/// # Errors
+///
+/// If some message cannot be converted,
+/// in which case the first error is returned.
+#[inline]
+pub fn try_into_tiktoken_rs_vec<M>(
+    messages: impl IntoIterator<Item = M>,
+) -> Result<Vec<tiktoken_rs::ChatCompletionRequestMessage>, ConversionError>
+where
+    M: IntoChatCompletionRequestMessage,
+{
+    messages
+        .into_iter()
+        .map(IntoChatCompletionRequestMessage::try_into_tiktoken_rs)
+        .collect()
+}
+
+/// Convert messages to [`async_openai` chat completion request message
+/// type](`async_openai::types::ChatCompletionRequestMessage`).
+///
+/// # Panics
+///
+/// If some message cannot be converted.
+#[inline]
+#[cfg(feature = "async-openai")]
+pub fn into_async_openai_vec<M>(
+    messages: impl IntoIterator<Item = M>,
+) -> Vec<async_openai::types::ChatCompletionRequestMessage>
+where
+    M: IntoChatCompletionRequestMessage,
+{
+    messages
+        .into_iter()
+        .map(IntoChatCompletionRequestMessage::into_async_openai)
+        .collect()
+}
+
+/// Try to convert messages to [`async_openai` chat completion request message
+/// type](`async_openai::types::ChatCompletionRequestMessage`).
+///
+/// # Errors
+///
+/// If some message cannot be converted,
+/// in which case the first error is returned.
+#[inline]
+#[cfg(feature = "async-openai")]
+pub fn try_into_async_openai_vec<M>(
+    messages: impl IntoIterator<Item = M>,
+) -> Result<Vec<async_openai::types::ChatCompletionRequestMessage>, ConversionError>
+where
+    M: IntoChatCompletionRequestMessage,
+{
+    messages
+        .into_iter()
+        .map(IntoChatCompletionRequestMessage::try_into_async_openai)
+        .collect()
+}
+
+/// Assemble streamed deltas into a single [`async_openai` chat completion
+/// response message
+/// type](`async_openai::types::ChatCompletionResponseMessage`).
+///
+/// Contents are concatenated,
+/// and
+/// tool calls are merged by index,
+/// so that the assembled message can be stored back into the chat,
+/// e.g.,
+/// after converting it with
+/// [`IntoChatCompletionRequestMessage::into_async_openai`].
+/// The role defaults to `assistant` if no delta has one.
+#[inline]
+#[allow(deprecated)]
+#[cfg(feature = "async-openai")]
+pub fn assemble_stream_deltas(
+    deltas: impl IntoIterator<Item = async_openai::types::ChatCompletionStreamResponseDelta>,
+) -> async_openai::types::ChatCompletionResponseMessage {
+    let mut role = None;
+    let mut content: Option<String> = None;
+    let mut function_call: Option<async_openai::types::FunctionCall> = None;
+    let mut tool_calls: Vec<(i32, async_openai::types::ChatCompletionMessageToolCall)> = Vec::new();
+
+    for delta in deltas {
+        role = role.or(delta.role);
+        if let Some(chunk) = delta.content {
+            content.get_or_insert_with(String::new).push_str(&chunk);
+        }
+        if let Some(chunk) = delta.function_call {
+            let function_call =
+                function_call.get_or_insert_with(|| async_openai::types::FunctionCall {
+                    name: String::new(),
+                    arguments: String::new(),
+                });
+            function_call.name.push_str(&chunk.name.unwrap_or_default());
+            function_call
+                .arguments
+                .push_str(&chunk.arguments.unwrap_or_default());
+        }
+        for chunk in delta.tool_calls.unwrap_or_default() {
+            let position = match tool_calls
+                .iter()
+                .position(|(index, _)| *index == chunk.index)
+            {
+                Some(position) => position,
+                None => {
+                    tool_calls.push((
+                        chunk.index,
+                        async_openai::types::ChatCompletionMessageToolCall {
+                            id: String::new(),
+                            r#type: async_openai::types::ChatCompletionToolType::Function,
+                            function: async_openai::types::FunctionCall {
+                                name: String::new(),
+                                arguments: String::new(),
+                            },
+                        },
+                    ));
+                    tool_calls.len() - 1
+                }
+            };
+            let tool_call = &mut tool_calls[position].1;
+            tool_call.id.push_str(&chunk.id.unwrap_or_default());
+            if let Some(function) = chunk.function {
+                tool_call
+                    .function
+                    .name
+                    .push_str(&function.name.unwrap_or_default());
+                tool_call
+                    .function
+                    .arguments
+                    .push_str(&function.arguments.unwrap_or_default());
+            }
+        }
+    }
+
+    async_openai::types::ChatCompletionResponseMessage {
+        content,
+        tool_calls: (!tool_calls.is_empty()).then(|| {
+            tool_calls
+                .into_iter()
+                .map(|(_, tool_call)| tool_call)
+                .collect()
+        }),
+        role: role.unwrap_or(async_openai::types::Role::Assistant),
+        function_call,
+    }
+}
+
+/// Get the role of a message.
+///
+/// # Errors
+///
+/// If the message cannot be converted for tokenization.
+#[inline]
+pub(crate) fn role<M>(message: &M) -> Result<String, SplitError>
+where
+    M: IntoChatCompletionRequestMessage + Clone,
+{
+    Ok(message.clone().try_into_tiktoken_rs()?.role)
+}
+
+/// Check whether the given role gives instructions to the model,
+/// i.e.,
+/// `system`,
+/// or
+/// `developer` for newer models.
+#[inline]
+pub(crate) fn is_system_role(role: &str) -> bool {
+    matches!(role, "system" | "developer")
+}
+
+/// Append tool calls to the content of a message.
+///
+/// [`tiktoken_rs`] only counts the content and name of a message,
+/// so tool calls would otherwise not be counted at all.
+#[inline]
+#[cfg(feature = "async-openai")]
+fn content_with_tool_calls(
+    content: Option<String>,
+    tool_calls: Option<Vec<async_openai::types::ChatCompletionMessageToolCall>>,
+) -> Option<String> {
+    let Some(tool_calls) = tool_calls.filter(|tool_calls| !tool_calls.is_empty()) else {
+        return content;
+    };
+
+    let mut content = content.unwrap_or_default();
+    for tool_call in tool_calls {
+        if !content.is_empty() {
+            content.push('\n');
+        }
+        content.push_str(&tool_call.id);
+        content.push('\n');
+        content.push_str(&tool_call.function.name);
+        content.push('\n');
+        content.push_str(&tool_call.function.arguments);
+    }
+    Some(content)
+}
+
+/// Extension trait for converting between different chat completion request
+/// message types.
+///
+/// For a usage example,
+/// see [`examples/chat.rs`](https://github.com/schneiderfelipe/chat-splitter/blob/736f4fceb57bc12adb2b70deb990030a266a95a5/examples/chat.rs#L44-L55).
+pub trait IntoChatCompletionRequestMessage {
+    /// Convert to [`tiktoken_rs` chat completion request message
+    /// type](`tiktoken_rs::ChatCompletionRequestMessage`).
+    ///
+    /// Implementations should keep the `name` of the message,
+    /// since it takes tokens too.
+    fn into_tiktoken_rs(self) -> tiktoken_rs::ChatCompletionRequestMessage;
+
+    /// Convert to [`async_openai` chat completion request message
+    /// type](`async_openai::types::ChatCompletionRequestMessage`).
+    ///
+    /// The default implementation calls
+    /// [`IntoChatCompletionRequestMessage::try_into_async_openai`].
+    ///
+    /// # Panics
+    ///
+    /// If the message has a role that cannot be converted.
+    #[inline]
+    #[cfg(feature = "async-openai")]
+    fn into_async_openai(self) -> async_openai::types::ChatCompletionRequestMessage
+    where
+        Self: Sized,
+    {
+        self.try_into_async_openai()
+            .unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// Try to convert to [`async_openai` chat completion request message
+    /// type](`async_openai::types::ChatCompletionRequestMessage`).
+    ///
+    /// The default implementation converts through
+    /// [`IntoChatCompletionRequestMessage::try_into_tiktoken_rs`],
+    /// so that implementations written without the `async-openai` feature
+    /// keep compiling when it is enabled,
+    /// but keeps only what [`tiktoken_rs`] messages can hold.
+    ///
+    /// # Errors
+    ///
+    /// If the message has a role that cannot be converted.
+    #[inline]
+    #[cfg(feature = "async-openai")]
+    fn try_into_async_openai(
+        self,
+    ) -> Result<async_openai::types::ChatCompletionRequestMessage, ConversionError>
+    where
+        Self: Sized,
+    {
+        self.try_into_tiktoken_rs()?.try_into_async_openai()
+    }
+
+    /// Try to convert to [`tiktoken_rs` chat completion request message
+    /// type](`tiktoken_rs::ChatCompletionRequestMessage`).
+    ///
+    /// The default implementation never fails and simply calls
+    /// [`IntoChatCompletionRequestMessage::into_tiktoken_rs`].
+    ///
+    /// # Errors
+    ///
+    /// If the message has a role that cannot be converted.
+    #[inline]
+    fn try_into_tiktoken_rs(
+        self,
+    ) -> Result<tiktoken_rs::ChatCompletionRequestMessage, ConversionError>
+    where
+        Self: Sized,
+    {
+        Ok(self.into_tiktoken_rs())
+    }
+
+    /// Get the number of images in the message,
+    /// which are not tokenized but estimated separately
+    /// (see [`ChatSplitter::image_tokens`](crate::ChatSplitter::image_tokens)).
+    ///
+    /// The default implementation returns zero.
+    #[inline]
+    fn image_count(&self) -> usize {
+        0
+    }
+
+    /// Get mutable access to the text content of the message,
+    /// if any,
+    /// e.g.,
+    /// for truncation
+    /// (see [`ChatSplitter::truncate_oversized`](crate::ChatSplitter::truncate_oversized)).
+    ///
+    /// The default implementation returns `None`.
+    #[inline]
+    fn content_mut(&mut self) -> Option<&mut String> {
+        None
+    }
+}
+
+/// Object-safe companion of [`IntoChatCompletionRequestMessage`],
+/// converting by reference.
+///
+/// It is implemented for every cloneable message type,
+/// and
+/// references to it implement [`IntoChatCompletionRequestMessage`],
+/// so that heterogeneous messages behind trait objects can be split,
+/// e.g.,
+/// as a slice of `&dyn ToChatCompletionRequestMessage`.
+/// Since messages are only ever borrowed,
+/// their content cannot be truncated
+/// (see [`ChatSplitter::truncate_oversized`](crate::ChatSplitter::truncate_oversized)).
+pub trait ToChatCompletionRequestMessage {
+    /// Try to convert to [`tiktoken_rs` chat completion request message
+    /// type](`tiktoken_rs::ChatCompletionRequestMessage`).
+    ///
+    /// # Errors
+    ///
+    /// If the message has a role that cannot be converted.
+    fn to_tiktoken_rs(&self) -> Result<tiktoken_rs::ChatCompletionRequestMessage, ConversionError>;
+
+    /// Try to convert to [`async_openai` chat completion request message
+    /// type](`async_openai::types::ChatCompletionRequestMessage`).
+    ///
+    /// The default implementation converts through
+    /// [`ToChatCompletionRequestMessage::to_tiktoken_rs`],
+    /// as in [`IntoChatCompletionRequestMessage::try_into_async_openai`].
+    ///
+    /// # Errors
+    ///
+    /// If the message has a role that cannot be converted.
+    #[inline]
+    #[cfg(feature = "async-openai")]
+    fn to_async_openai(
+        &self,
+    ) -> Result<async_openai::types::ChatCompletionRequestMessage, ConversionError> {
+        self.to_tiktoken_rs()?.try_into_async_openai()
+    }
+
+    /// Get the number of images in the message
+    /// (see [`IntoChatCompletionRequestMessage::image_count`]).
+    fn count_images(&self) -> usize;
+}
+
+impl<M> ToChatCompletionRequestMessage for M
+where
+    M: IntoChatCompletionRequestMessage + Clone,
+{
+    #[inline]
+    fn to_tiktoken_rs(&self) -> Result<tiktoken_rs::ChatCompletionRequestMessage, ConversionError> {
+        self.clone().try_into_tiktoken_rs()
+    }
+
+    #[inline]
+    #[cfg(feature = "async-openai")]
+    fn to_async_openai(
+        &self,
+    ) -> Result<async_openai::types::ChatCompletionRequestMessage, ConversionError> {
+        self.clone().try_into_async_openai()
+    }
+
+    #[inline]
+    fn count_images(&self) -> usize {
+        self.image_count()
+    }
+}
+
+impl<T> IntoChatCompletionRequestMessage for &T
+where
+    T: ToChatCompletionRequestMessage + ?Sized,
+{
+    #[inline]
+    fn into_tiktoken_rs(self) -> tiktoken_rs::ChatCompletionRequestMessage {
+        self.to_tiktoken_rs()
+            .unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    #[inline]
+    #[cfg(feature = "async-openai")]
+    fn try_into_async_openai(
+        self,
+    ) -> Result<async_openai::types::ChatCompletionRequestMessage, ConversionError> {
+        self.to_async_openai()
+    }
+
+    #[inline]
+    fn try_into_tiktoken_rs(
+        self,
+    ) -> Result<tiktoken_rs::ChatCompletionRequestMessage, ConversionError> {
+        self.to_tiktoken_rs()
+    }
+
+    #[inline]
+    fn image_count(&self) -> usize {
+        (**self).count_images()
+    }
+}
+
+/// Messages that report the number of tokens they take,
+/// e.g.,
+/// from a cache,
+/// as used by [`ChatSplitter::try_split_counted`](crate::ChatSplitter::try_split_counted).
+///
+/// Counts for messages without one at hand are best taken from
+/// [`ChatSplitter::message_tokens`](crate::ChatSplitter::message_tokens),
+/// which builds the tokenizer only once.
+pub trait TokenCounted {
+    /// Get the number of tokens the message takes for the given model,
+    /// including any per-message overhead.
+    fn token_count(&self, model: &str) -> usize;
+}
+
+impl IntoChatCompletionRequestMessage for tiktoken_rs::ChatCompletionRequestMessage {
+    #[inline]
+    fn into_tiktoken_rs(self) -> tiktoken_rs::ChatCompletionRequestMessage {
+        self
+    }
+
+    #[inline]
+    fn content_mut(&mut self) -> Option<&mut String> {
+        self.content.as_mut()
+    }
+
+    #[inline]
+    #[allow(deprecated)]
+    #[cfg(feature = "async-openai")]
+    fn try_into_async_openai(
+        self,
+    ) -> Result<async_openai::types::ChatCompletionRequestMessage, ConversionError> {
+        let role = parse_role(&self.role)?;
+        let message = match role {
+            async_openai::types::Role::User => {
+                async_openai::types::ChatCompletionRequestMessage::User(
+                    async_openai::types::ChatCompletionRequestUserMessage {
+                        role,
+                        content: match self.content {
+                            Some(text) => {
+                                async_openai::types::ChatCompletionRequestUserMessageContent::Text(
+                                    text,
+                                )
+                            }
+                            None => {
+                                async_openai::types::ChatCompletionRequestUserMessageContent::Array(
+                                    Vec::new(),
+                                )
+                            }
+                        },
+                        name: self.name,
+                    },
+                )
+            }
+            async_openai::types::Role::System => {
+                async_openai::types::ChatCompletionRequestMessage::System(
+                    async_openai::types::ChatCompletionRequestSystemMessage {
+                        role,
+                        // Missing content is treated as empty.
+                        content: self.content.unwrap_or_default(),
+                        name: self.name,
+                    },
+                )
+            }
+            async_openai::types::Role::Assistant => {
+                async_openai::types::ChatCompletionRequestMessage::Assistant(
+                    async_openai::types::ChatCompletionRequestAssistantMessage {
+                        role,
+                        content: self.content,
+                        // `tiktoken_rs` messages have no tool calls to preserve:
+                        // at most,
+                        // they were appended to the content for counting.
+                        tool_calls: None,
+                        function_call: self.function_call.map(|fc| {
+                            async_openai::types::FunctionCall {
+                                name: fc.name,
+                                arguments: fc.arguments,
+                            }
+                        }),
+                        name: self.name,
+                    },
+                )
+            }
+            async_openai::types::Role::Function => {
+                async_openai::types::ChatCompletionRequestMessage::Function(
+                    async_openai::types::ChatCompletionRequestFunctionMessage {
+                        role,
+                        content: self.content,
+                        name: self.name.unwrap_or_default(),
+                    },
+                )
+            }
+            async_openai::types::Role::Tool => {
+                async_openai::types::ChatCompletionRequestMessage::Tool(
+                    async_openai::types::ChatCompletionRequestToolMessage {
+                        role,
+                        content: self.content.unwrap_or_default(),
+                        // The tool call ID is kept as the name,
+                        // as in the conversion to `tiktoken_rs`.
+                        tool_call_id: self.name.unwrap_or_default(),
+                    },
+                )
+            }
+        };
+        Ok(message)
+    }
+}
+
+/// Parse the role of a [`tiktoken_rs`] message,
+/// e.g.,
+/// `user`.
+///
+/// # Errors
+///
+/// If the role is not known to [`async_openai`].
+#[inline]
+#[cfg(feature = "async-openai")]
+pub(crate) fn parse_role(role: &str) -> Result<async_openai::types::Role, ConversionError> {
+    match role {
+        "user" => Ok(async_openai::types::Role::User),
+        // `async_openai` has no `developer` role yet,
+        // and
+        // the API accepts `system` in its place.
+        "system" | "developer" => Ok(async_openai::types::Role::System),
+        "assistant" => Ok(async_openai::types::Role::Assistant),
+        "function" => Ok(async_openai::types::Role::Function),
+        "tool" => Ok(async_openai::types::Role::Tool),
+        role => Err(ConversionError::UnsupportedRole(role.to_owned())),
+    }
+}
+
+#[cfg(feature = "async-openai")]
+impl IntoChatCompletionRequestMessage for async_openai::types::ChatCompletionRequestMessage {
+    #[inline]
+    fn into_tiktoken_rs(self) -> tiktoken_rs::ChatCompletionRequestMessage {
+        self.try_into_tiktoken_rs()
+            .unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    #[inline]
+    fn into_async_openai(self) -> async_openai::types::ChatCompletionRequestMessage {
+        self
+    }
+
+    #[inline]
+    fn try_into_async_openai(
+        self,
+    ) -> Result<async_openai::types::ChatCompletionRequestMessage, ConversionError> {
+        Ok(self)
+    }
+
+    #[inline]
+    #[allow(deprecated)]
+    fn try_into_tiktoken_rs(
+        self,
+    ) -> Result<tiktoken_rs::ChatCompletionRequestMessage, ConversionError> {
+        let message = match self {
+            async_openai::types::ChatCompletionRequestMessage::User(message) => {
+                tiktoken_rs::ChatCompletionRequestMessage {
+                    role: message.role.to_string(),
+                    content: match message.content {
+                        async_openai::types::ChatCompletionRequestUserMessageContent::Text(
+                            text,
+                        ) => Some(text),
+                        async_openai::types::ChatCompletionRequestUserMessageContent::Array(
+                            array,
+                        ) => {
+                            let texts: Vec<_> = array
+                                .into_iter()
+                                .filter_map(|part| match part {
+                                    async_openai::types::ChatCompletionRequestMessageContentPart::Text(
+                                        part,
+                                    ) => Some(part.text),
+                                    async_openai::types::ChatCompletionRequestMessageContentPart::Image(
+                                        _,
+                                    ) => None,
+                                })
+                                .collect();
+                            if texts.is_empty() {
+                                None
+                            } else {
+                                Some(texts.join("\n"))
+                            }
+                        }
+                    },
+                    function_call: None,
+                    name: message.name,
+                }
+            }
+            async_openai::types::ChatCompletionRequestMessage::System(message) => {
+                tiktoken_rs::ChatCompletionRequestMessage {
+                    role: message.role.to_string(),
+                    content: Some(message.content),
+                    function_call: None,
+                    name: message.name,
+                }
+            }
+            async_openai::types::ChatCompletionRequestMessage::Assistant(message) => {
+                tiktoken_rs::ChatCompletionRequestMessage {
+                    role: message.role.to_string(),
+                    content: content_with_tool_calls(message.content, message.tool_calls),
+                    function_call: message.function_call.map(|fc| tiktoken_rs::FunctionCall {
+                        name: fc.name,
+                        arguments: fc.arguments,
+                    }),
+                    name: message.name,
+                }
+            }
+            async_openai::types::ChatCompletionRequestMessage::Function(message) => {
+                tiktoken_rs::ChatCompletionRequestMessage {
+                    role: message.role.to_string(),
+                    content: message.content,
+                    function_call: None,
+                    name: Some(message.name),
+                }
+            }
+            async_openai::types::ChatCompletionRequestMessage::Tool(message) => {
+                tiktoken_rs::ChatCompletionRequestMessage {
+                    role: message.role.to_string(),
+                    content: Some(message.content),
+                    function_call: None,
+                    // `tiktoken_rs` has no field for the tool call ID,
+                    // so it is counted as the name.
+                    name: Some(message.tool_call_id),
+                }
+            }
+        };
+        Ok(message)
+    }
+
+    #[inline]
+    fn image_count(&self) -> usize {
+        match self {
+            async_openai::types::ChatCompletionRequestMessage::User(
+                async_openai::types::ChatCompletionRequestUserMessage {
+                    content:
+                        async_openai::types::ChatCompletionRequestUserMessageContent::Array(array),
+                    ..
+                },
+            ) => array
+                .iter()
+                .filter(|part| {
+                    matches!(
+                        part,
+                        async_openai::types::ChatCompletionRequestMessageContentPart::Image(_)
+                    )
+                })
+                .count(),
+            _ => 0,
+        }
+    }
+
+    #[inline]
+    fn content_mut(&mut self) -> Option<&mut String> {
+        match self {
+            async_openai::types::ChatCompletionRequestMessage::System(message) => {
+                Some(&mut message.content)
+            }
+            async_openai::types::ChatCompletionRequestMessage::User(
+                async_openai::types::ChatCompletionRequestUserMessage {
+                    content:
+                        async_openai::types::ChatCompletionRequestUserMessageContent::Text(text),
+                    ..
+                },
+            ) => Some(text),
+            async_openai::types::ChatCompletionRequestMessage::User(_) => None,
+            async_openai::types::ChatCompletionRequestMessage::Assistant(message) => {
+                message.content.as_mut()
+            }
+            async_openai::types::ChatCompletionRequestMessage::Tool(message) => {
+                Some(&mut message.content)
+            }
+            async_openai::types::ChatCompletionRequestMessage::Function(message) => {
+                message.content.as_mut()
+            }
+        }
+    }
+}
+
+#[cfg(feature = "async-openai")]
+impl IntoChatCompletionRequestMessage for async_openai::types::ChatCompletionResponseMessage {
+    #[inline]
+    #[allow(deprecated)]
+    fn into_tiktoken_rs(self) -> tiktoken_rs::ChatCompletionRequestMessage {
+        tiktoken_rs::ChatCompletionRequestMessage {
+            role: self.role.to_string(),
+            content: content_with_tool_calls(self.content, self.tool_calls),
+            function_call: self.function_call.map(|fc| tiktoken_rs::FunctionCall {
+                name: fc.name,
+                arguments: fc.arguments,
+            }),
+            name: None,
+        }
+    }
+
+    #[inline]
+    fn content_mut(&mut self) -> Option<&mut String> {
+        self.content.as_mut()
+    }
+
+    #[inline]
+    #[allow(deprecated)]
+    fn try_into_async_openai(
+        self,
+    ) -> Result<async_openai::types::ChatCompletionRequestMessage, ConversionError> {
+        let message = match self.role {
+            async_openai::types::Role::User => {
+                async_openai::types::ChatCompletionRequestMessage::User(
+                    async_openai::types::ChatCompletionRequestUserMessage {
+                        role: self.role,
+                        content: match self.content {
+                            Some(text) => {
+                                async_openai::types::ChatCompletionRequestUserMessageContent::Text(
+                                    text,
+                                )
+                            }
+                            None => {
+                                async_openai::types::ChatCompletionRequestUserMessageContent::Array(
+                                    Vec::new(),
+                                )
+                            }
+                        },
+                        name: None,
+                    },
+                )
+            }
+            async_openai::types::Role::System => {
+                async_openai::types::ChatCompletionRequestMessage::System(
+                    async_openai::types::ChatCompletionRequestSystemMessage {
+                        role: self.role,
+                        // Missing content is treated as empty.
+                        content: self.content.unwrap_or_default(),
+                        name: None,
+                    },
+                )
+            }
+            async_openai::types::Role::Assistant => {
+                async_openai::types::ChatCompletionRequestMessage::Assistant(
+                    async_openai::types::ChatCompletionRequestAssistantMessage {
+                        role: self.role,
+                        content: self.content,
+                        tool_calls: self.tool_calls,
+                        function_call: self.function_call,
+                        name: None,
+                    },
+                )
+            }
+            async_openai::types::Role::Function => {
+                async_openai::types::ChatCompletionRequestMessage::Function(
+                    async_openai::types::ChatCompletionRequestFunctionMessage {
+                        role: self.role,
+                        content: self.content,
+                        name: self
+                            .function_call
+                            .map(|function_call| function_call.name)
+                            .unwrap_or_default(),
+                    },
+                )
+            }
+            // Responses carry no tool call ID to answer.
+            role @ async_openai::types::Role::Tool => {
+                return Err(ConversionError::UnsupportedRole(role.to_string()));
+            }
+        };
+        Ok(message)
+    }
+}
+
+/// A lightweight chat message,
+/// e.g.,
+/// for prototyping and tests.
+///
+/// # Examples
+///
+/// ```
+/// use chat_splitter::ChatSplitter;
+/// use chat_splitter::SimpleMessage;
+///
+/// let messages = [
+///     SimpleMessage::new("user", "Who won the world series in 2020?"),
+///     SimpleMessage::new("assistant", "The Los Angeles Dodgers."),
+/// ];
+/// let (outdated, recent) = ChatSplitter::default().max_messages(1_usize).split(&messages);
+/// assert_eq!(outdated, &messages[..1]);
+/// assert_eq!(recent, &messages[1..]);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SimpleMessage {
+    /// The role of the message,
+    /// e.g.,
+    /// `user`.
+    pub role: String,
+
+    /// The text content of the message.
+    pub content: String,
+}
+
+impl SimpleMessage {
+    /// Create a new [`SimpleMessage`] with the given role and content.
+    #[inline]
+    pub fn new(role: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            role: role.into(),
+            content: content.into(),
+        }
+    }
+}
+
+/// Messages are converted through [`tiktoken_rs`],
+/// so converting to [`async_openai`](https://github.com/64bit/async-openai) fails on roles other than `user`,
+/// `system`,
+/// `developer`
+/// (as `system`),
+/// `assistant`,
+/// `function`
+/// and
+/// `tool`
+/// (with an empty tool call ID).
+impl IntoChatCompletionRequestMessage for SimpleMessage {
+    #[inline]
+    fn into_tiktoken_rs(self) -> tiktoken_rs::ChatCompletionRequestMessage {
+        tiktoken_rs::ChatCompletionRequestMessage {
+            role: self.role,
+            content: Some(self.content),
+            name: None,
+            function_call: None,
+        }
+    }
+
+    #[inline]
+    fn content_mut(&mut self) -> Option<&mut String> {
+        Some(&mut self.content)
+    }
+}
+
+/// Role and content pairs are converted like [`SimpleMessage`].
+///
+/// # Examples
+///
+/// ```
+/// use chat_splitter::ChatSplitter;
+///
+/// let messages = [
+///     ("user", "Who won the world series in 2020?"),
+///     ("assistant", "The Los Angeles Dodgers."),
+/// ];
+/// let (outdated, recent) = ChatSplitter::default().split(&messages);
+/// assert!(outdated.is_empty());
+/// assert_eq!(recent, messages);
+/// ```
+impl<R, C> IntoChatCompletionRequestMessage for (R, C)
+where
+    R: Into<String>,
+    C: Into<String>,
+{
+    #[inline]
+    fn into_tiktoken_rs(self) -> tiktoken_rs::ChatCompletionRequestMessage {
+        SimpleMessage::new(self.0, self.1).into_tiktoken_rs()
+    }
+}
+
+#[cfg(all(test, feature = "async-openai"))]
+mod tests {
+    use super::*;
+    use crate::tests::conversation;
+    use crate::tests::tool_message;
+    use crate::tests::user_message;
+    use crate::tests::TenTokensPerMessage;
+    use crate::ChatSplitter;
+
+    #[test]
+    fn vec_conversions_round_trip() {
+        let messages = conversation(2);
+
+        let converted = into_tiktoken_rs_vec(messages.clone());
+        assert_eq!(
+            try_into_tiktoken_rs_vec(messages.clone()),
+            Ok(converted.clone())
+        );
+        assert_eq!(converted[1].role, "assistant");
+        assert_eq!(into_async_openai_vec(converted), messages);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn stream_deltas_are_assembled() {
+        let delta = |content: &str, arguments: &str| {
+            async_openai::types::ChatCompletionStreamResponseDelta {
+                content: Some(content.to_owned()),
+                function_call: None,
+                tool_calls: Some(vec![
+                    async_openai::types::ChatCompletionMessageToolCallChunk {
+                        index: 0,
+                        id: None,
+                        r#type: None,
+                        function: Some(async_openai::types::FunctionCallStream {
+                            name: None,
+                            arguments: Some(arguments.to_owned()),
+                        }),
+                    },
+                ]),
+                role: None,
+            }
+        };
+
+        let message = assemble_stream_deltas([delta("Hel", "{\"a\""), delta("lo", ": 1}")]);
+        assert_eq!(message.role, async_openai::types::Role::Assistant);
+        assert_eq!(message.content.as_deref(), Some("Hello"));
+        assert_eq!(
+            message.tool_calls.as_ref().unwrap()[0].function.arguments,
+            "{\"a\": 1}"
+        );
+
+        let async_openai::types::ChatCompletionRequestMessage::Assistant(message) =
+            message.into_async_openai()
+        else {
+            panic!("expected an assistant message");
+        };
+        assert_eq!(message.tool_calls.unwrap().len(), 1);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn response_tool_calls_round_trip() {
+        let tool_calls = vec![async_openai::types::ChatCompletionMessageToolCall {
+            id: "call_abc123".to_owned(),
+            r#type: async_openai::types::ChatCompletionToolType::Function,
+            function: async_openai::types::FunctionCall {
+                name: "get_current_weather".to_owned(),
+                arguments: r#"{"location": "Boston, MA"}"#.to_owned(),
+            },
+        }];
+        let response = async_openai::types::ChatCompletionResponseMessage {
+            content: None,
+            tool_calls: Some(tool_calls.clone()),
+            role: async_openai::types::Role::Assistant,
+            function_call: None,
+        };
+
+        let request = response.into_async_openai();
+        let async_openai::types::ChatCompletionRequestMessage::Assistant(assistant) = &request
+        else {
+            panic!("expected an assistant message");
+        };
+        assert_eq!(assistant.tool_calls.as_ref(), Some(&tool_calls));
+
+        // Request messages are left as they are.
+        assert_eq!(request.clone().into_async_openai(), request);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn empty_system_and_function_messages_convert() {
+        let system = tiktoken_rs::ChatCompletionRequestMessage {
+            role: "system".to_owned(),
+            ..Default::default()
+        };
+        let async_openai::types::ChatCompletionRequestMessage::System(message) =
+            system.clone().into_async_openai()
+        else {
+            panic!("expected a system message");
+        };
+        assert_eq!(message.content, "");
+
+        let function = tiktoken_rs::ChatCompletionRequestMessage {
+            role: "function".to_owned(),
+            ..Default::default()
+        };
+        let async_openai::types::ChatCompletionRequestMessage::Function(message) =
+            function.clone().into_async_openai()
+        else {
+            panic!("expected a function message");
+        };
+        assert_eq!(message.name, "");
+        assert_eq!(message.content, None);
+
+        for role in [
+            async_openai::types::Role::System,
+            async_openai::types::Role::Function,
+        ] {
+            let response = async_openai::types::ChatCompletionResponseMessage {
+                content: None,
+                tool_calls: None,
+                role,
+                function_call: None,
+            };
+            assert_eq!(
+                response.into_async_openai().into_tiktoken_rs().role,
+                role.to_string()
+            );
+        }
+
+        let splitter = ChatSplitter::new("gpt-4");
+        assert!(splitter.count_tokens(&[system, function]).is_ok());
+    }
+
+    #[test]
+    fn simple_messages_convert() {
+        let message = SimpleMessage::new("user", "Hello!");
+        let expected = user_message("Hello!");
+        assert_eq!(message.clone().into_async_openai(), expected);
+        assert_eq!(
+            message.clone().into_tiktoken_rs(),
+            expected.clone().into_tiktoken_rs()
+        );
+        assert_eq!(("user", "Hello!").into_async_openai(), expected);
+        assert_eq!(
+            (String::from("user"), String::from("Hello!")).into_tiktoken_rs(),
+            message.into_tiktoken_rs()
+        );
+    }
+
+    #[test]
+    fn roles_are_parsed() {
+        for role in [
+            async_openai::types::Role::User,
+            async_openai::types::Role::System,
+            async_openai::types::Role::Assistant,
+            async_openai::types::Role::Function,
+            async_openai::types::Role::Tool,
+        ] {
+            assert_eq!(parse_role(&role.to_string()), Ok(role));
+        }
+        assert_eq!(
+            parse_role("moderator"),
+            Err(ConversionError::UnsupportedRole("moderator".to_owned()))
+        );
+        assert_eq!(
+            parse_role("User"),
+            Err(ConversionError::UnsupportedRole("User".to_owned()))
+        );
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn unsupported_roles_fail_to_convert() {
+        let moderator = SimpleMessage::new("moderator", "Be nice.");
+        assert_eq!(
+            moderator.clone().try_into_async_openai(),
+            Err(ConversionError::UnsupportedRole("moderator".to_owned()))
+        );
+        assert_eq!(
+            try_into_async_openai_vec([SimpleMessage::new("user", "Hi!"), moderator.clone()]),
+            Err(ConversionError::UnsupportedRole("moderator".to_owned()))
+        );
+
+        let messages = [
+            SimpleMessage::new("user", "Hi!"),
+            SimpleMessage::new("assistant", "Hello!"),
+            moderator,
+        ];
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(50_u16);
+        assert_eq!(
+            splitter.try_recent_async_openai(&messages),
+            Err(SplitError::UnsupportedRole("moderator".to_owned()))
+        );
+
+        let response = async_openai::types::ChatCompletionResponseMessage {
+            role: async_openai::types::Role::Tool,
+            content: Some("42".to_owned()),
+            tool_calls: None,
+            function_call: None,
+        };
+        assert_eq!(
+            response.try_into_async_openai(),
+            Err(ConversionError::UnsupportedRole("tool".to_owned()))
+        );
+    }
+
+    #[test]
+    fn tool_messages_round_trip() {
+        let message = tool_message(r#"{"temperature": 22, "unit": "celsius"}"#, "call_abc123");
+        let converted = message.clone().into_tiktoken_rs();
+        assert_eq!(converted.role, "tool");
+        assert_eq!(converted.name.as_deref(), Some("call_abc123"));
+        assert_eq!(converted.into_async_openai(), message);
+
+        assert_eq!(
+            ("tool", "22 degrees").into_async_openai(),
+            tool_message("22 degrees", "")
+        );
+    }
+}
@@ -0,0 +1,249 @@
+//! Splitting one new message at a time.
+
+use std::ops::Range;
+use std::slice;
+
+use crate::ChatSplitter;
+use crate::IntoChatCompletionRequestMessage;
+use crate::SplitError;
+
+/// Chat splitter that accepts one new message at a time.
+///
+/// This keeps only the 'recent' window,
+/// together with the tokens each of its messages takes on its own,
+/// so that every new message is tokenized only once.
+/// Coalescing
+/// (see [`ChatSplitter::coalesce_consecutive`])
+/// and
+/// separators
+/// (see [`ChatSplitter::message_separator_tokens`])
+/// depend on the neighbors of each message,
+/// so they are counted over the whole window on every push.
+/// Where 'outdated' messages would have been coalesced with 'recent' ones,
+/// the 'recent' ones are counted as sent without them,
+/// which can only outdate more messages than [`ChatSplitter::split`].
+/// The 'recent' window always satisfies the same limits as
+/// [`ChatSplitter::split`] would give,
+/// but messages,
+/// once 'outdated',
+/// are never brought back,
+/// e.g.,
+/// by [`ChatSplitter::min_messages`].
+///
+/// The configuration of the underlying [`ChatSplitter`] cannot be changed:
+/// to use different limits,
+/// build a new [`IncrementalSplitter`] and push the 'recent' messages again.
+#[derive(Clone, Debug)]
+pub struct IncrementalSplitter<M> {
+    /// The underlying chat splitter.
+    splitter: ChatSplitter,
+
+    /// The current 'recent' messages.
+    recent: Vec<M>,
+
+    /// The current 'recent' messages,
+    /// converted for tokenization.
+    converted: Vec<tiktoken_rs::ChatCompletionRequestMessage>,
+
+    /// The tokens each of the current 'recent' messages takes on its own,
+    /// before coalescing and separators.
+    tokens: Vec<usize>,
+
+    /// The tokens the images of each of the current 'recent' messages take.
+    image_tokens: Vec<usize>,
+
+    /// Whether messages were ever 'outdated',
+    /// so that the first 'recent' message follows a separator.
+    trimmed: bool,
+}
+
+impl<M> IncrementalSplitter<M> {
+    /// Create a new [`IncrementalSplitter`] with an empty 'recent' window.
+    #[inline]
+    pub fn new(splitter: ChatSplitter) -> Self {
+        Self {
+            splitter,
+            recent: Vec::new(),
+            converted: Vec::new(),
+            tokens: Vec::new(),
+            image_tokens: Vec::new(),
+            trimmed: false,
+        }
+    }
+
+    /// Get the current 'recent' messages.
+    #[inline]
+    pub fn recent(&self) -> &[M] {
+        &self.recent
+    }
+
+    /// Get the current 'recent' messages,
+    /// consuming the splitter.
+    #[inline]
+    pub fn into_recent(self) -> Vec<M> {
+        self.recent
+    }
+}
+
+impl<M> IncrementalSplitter<M>
+where
+    M: IntoChatCompletionRequestMessage + Clone,
+{
+    /// Push a new message.
+    ///
+    /// This is the same as [`IncrementalSplitter::try_push`],
+    /// but panics instead of returning an error.
+    ///
+    /// # Panics
+    ///
+    /// If tokenizer for the specified model is not found or is not a supported
+    /// chat model,
+    /// if the message cannot be converted for tokenization,
+    /// or if [`ChatSplitter::max_tokens`] is not smaller than the context size.
+    #[inline]
+    pub fn push(&mut self, message: M) -> Option<Vec<M>> {
+        self.try_push(message).unwrap()
+    }
+
+    /// Push a new message,
+    /// returning the messages that became 'outdated',
+    /// if any.
+    ///
+    /// # Errors
+    ///
+    /// If tokenizer for the specified model is not found or is not a supported
+    /// chat model
+    /// ([`SplitError::UnknownModel`]),
+    /// if the message cannot be converted for tokenization
+    /// ([`SplitError::UnsupportedRole`]),
+    /// or if [`ChatSplitter::max_tokens`] is not smaller than the context size
+    /// ([`SplitError::CompletionBudgetTooLarge`]).
+    /// In this case,
+    /// the message is not pushed.
+    #[inline]
+    pub fn try_push(&mut self, message: M) -> Result<Option<Vec<M>>, SplitError> {
+        let converted = message.clone().try_into_tiktoken_rs()?;
+        let tokens = if self.splitter.strategy.uses_tokens() {
+            self.splitter.using_tokenizer(|tokenizer| {
+                tokenizer.count_message_tokens(slice::from_ref(&converted))
+            })?[0]
+        } else {
+            0
+        };
+        let image_tokens = self
+            .splitter
+            .message_image_tokens(self.recent.len(), &message)?;
+        self.recent.push(message);
+        self.converted.push(converted);
+        self.tokens.push(tokens);
+        self.image_tokens.push(image_tokens);
+
+        let outdated = match self.outdated() {
+            Ok(outdated) => outdated,
+            Err(error) => {
+                self.recent.pop();
+                self.converted.pop();
+                self.tokens.pop();
+                self.image_tokens.pop();
+                return Err(error);
+            }
+        };
+        if outdated.is_empty() {
+            return Ok(None);
+        }
+        self.converted.drain(outdated.clone());
+        self.tokens.drain(outdated.clone());
+        self.image_tokens.drain(outdated.clone());
+        self.trimmed = true;
+        Ok(Some(self.recent.drain(outdated).collect()))
+    }
+
+    /// Get the range of 'outdated' messages in the current window.
+    ///
+    /// # Errors
+    ///
+    /// If tokenizer for the specified model is not found or is not a supported
+    /// chat model,
+    /// or if [`ChatSplitter::max_tokens`] is not smaller than the context size.
+    #[inline]
+    fn outdated(&self) -> Result<Range<usize>, SplitError> {
+        if !self.splitter.strategy.uses_tokens() {
+            return self.splitter.outdated_with_tokens(&self.recent, None);
+        }
+        let mut tokens = self
+            .splitter
+            .adjust_message_tokens(&self.converted, self.tokens.clone())?;
+        for (tokens, image_tokens) in tokens.iter_mut().zip(&self.image_tokens) {
+            *tokens += image_tokens;
+        }
+        if self.trimmed {
+            if let Some(tokens) = tokens.first_mut() {
+                *tokens += self.splitter.message_separator_tokens;
+            }
+        }
+        self.splitter
+            .outdated_with_tokens(&self.recent, Some(&tokens))
+    }
+}
+
+#[cfg(all(test, feature = "async-openai"))]
+mod tests {
+    use super::*;
+    use crate::tests::assistant_message;
+    use crate::tests::conversation;
+    use crate::tests::TenTokensPerMessage;
+
+    #[test]
+    fn incremental_splitter_agrees_with_split() {
+        let messages = conversation(6);
+        // Consecutive assistant messages,
+        // so that coalescing has something to do,
+        // while keeping pairs never cuts them.
+        let coalescible: Vec<_> = messages
+            .iter()
+            .enumerate()
+            .flat_map(|(i, message)| {
+                let mut messages = vec![message.clone()];
+                if i % 2 == 1 {
+                    messages.push(assistant_message("Anything else?"));
+                }
+                messages
+            })
+            .collect();
+
+        let ten_tokens = ChatSplitter::default().with_tokenizer(TenTokensPerMessage);
+        for (splitter, messages) in [
+            (
+                ten_tokens.clone().max_tokens(0_u16).max_messages(5_usize),
+                &messages,
+            ),
+            (
+                ten_tokens.clone().max_tokens(60_u16).keep_pairs(true),
+                &messages,
+            ),
+            (
+                ten_tokens
+                    .max_tokens(50_u16)
+                    .message_separator_tokens(2_usize),
+                &messages,
+            ),
+            (
+                ChatSplitter::new("gpt-4")
+                    .max_input_tokens(60_u16)
+                    .keep_pairs(true)
+                    .coalesce_consecutive(true),
+                &coalescible,
+            ),
+        ] {
+            let mut incremental = IncrementalSplitter::new(splitter.clone());
+            let mut outdated = Vec::new();
+            for (i, message) in messages.iter().enumerate() {
+                outdated.extend(incremental.push(message.clone()).unwrap_or_default());
+
+                let (expected_outdated, expected_recent) = splitter.split(&messages[..=i]);
+                assert_eq!(outdated, expected_outdated);
+                assert_eq!(incremental.recent(), expected_recent);
+            }
+        }
+    }
+}
@@ -35,6 +35,23 @@
 //! For a more detailed example,
 //! see [`examples/chat.rs`](https://github.com/schneiderfelipe/chat-splitter/blob/main/examples/chat.rs).
 //!
+//! # Features
+//!
+//! - `async-openai` (enabled by default):
+//!   conversions from and to [`async_openai`](https://github.com/64bit/async-openai) message types.
+//!   Without it,
+//!   chats can still be split as [`tiktoken_rs`] messages.
+//! - `serde`:
+//!   (de)serialization of [`ChatSplitter`] configurations.
+//! - `tokio`:
+//!   asynchronous splitting,
+//!   with tokenization offloaded to a blocking thread
+//!   (see `ChatSplitter::split_async`).
+//!
+//! [`tiktoken_rs`] itself is always required,
+//! since messages are converted to its message type for token counting,
+//! even with a custom tokenizer or estimator.
+//!
 //! # Contributing
 //!
 //! Contributions to `chat-splitter` are welcome!
@@ -43,17 +60,72 @@
 //! If you'd like to contribute code,
 //! please feel free to [submit a pull request](https://github.com/schneiderfelipe/chat-splitter/pulls).
 
+use std::borrow::Cow;
 use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::iter;
+use std::ops::Range;
+use std::slice;
+use std::str;
+use std::sync::Arc;
+use std::sync::OnceLock;
 
 use indxvec::Search;
-use tiktoken_rs::get_chat_completion_max_tokens;
-use tiktoken_rs::model::get_context_size;
+use tiktoken_rs::get_bpe_from_model;
+use tiktoken_rs::CoreBPE;
+
+pub use crate::caching::CachingSplitter;
+#[cfg(feature = "async-openai")]
+pub use crate::convert::assemble_stream_deltas;
+#[cfg(feature = "async-openai")]
+pub use crate::convert::into_async_openai_vec;
+pub use crate::convert::into_tiktoken_rs_vec;
+#[cfg(feature = "async-openai")]
+pub use crate::convert::try_into_async_openai_vec;
+pub use crate::convert::try_into_tiktoken_rs_vec;
+pub use crate::convert::IntoChatCompletionRequestMessage;
+pub use crate::convert::SimpleMessage;
+pub use crate::convert::ToChatCompletionRequestMessage;
+pub use crate::convert::TokenCounted;
+pub use crate::incremental::IncrementalSplitter;
+pub use crate::tokenizer::estimate_tokens;
+pub use crate::tokenizer::is_supported_model;
+pub use crate::tokenizer::supported_models;
+pub use crate::tokenizer::truncate_content_chars;
+pub use crate::tokenizer::Model;
+pub use crate::tokenizer::TiktokenTokenizer;
+pub use crate::tokenizer::Tokenizer;
 
-/// Chat splitter for [OpenAI](https://openai.com/)'s [chat models](https://platform.openai.com/docs/api-reference/chat) when using [`async_openai`].
+use crate::convert::is_system_role;
+use crate::convert::role;
+use crate::convert::try_into_tiktoken_rs;
+use crate::tokenizer::context_size;
+use crate::tokenizer::is_chat_model;
+use crate::tokenizer::warn_implausible_context_size;
+use crate::tokenizer::CachedTiktokenTokenizer;
+use crate::tokenizer::Estimator;
+
+mod caching;
+mod convert;
+mod incremental;
+mod tokenizer;
+
+/// Chat splitter for [OpenAI](https://openai.com/)'s [chat models](https://platform.openai.com/docs/api-reference/chat) when using [`async_openai`](https://github.com/64bit/async-openai).
 ///
 /// For more detailed information,
 /// see the [crate documentation](`crate`).
-#[derive(Clone, Debug)]
+///
+/// Two splitters are equal if they are configured the same way,
+/// where custom tokenizers and callbacks are only equal if they are the same
+/// instance,
+/// e.g.,
+/// shared by clones of a splitter.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct ChatSplitter {
     /// The model to use for tokenization,
     /// e.g.,
@@ -62,28 +134,579 @@ pub struct ChatSplitter {
     /// It is passed to [`tiktoken_rs`] to select the correct tokenizer.
     model: String,
 
+    /// The model to use for tokenization only,
+    /// if different from `model`.
+    tokenizer_model: Option<String>,
+
     /// The maximum number of tokens to leave for chat completion.
     ///
-    /// This is the same as in the [official API](https://platform.openai.com/docs/api-reference/chat#completions/create-prompt) and given to [`async_openai`].
+    /// This is the same as in the [official API](https://platform.openai.com/docs/api-reference/chat#completions/create-prompt) and given to [`async_openai`](https://github.com/64bit/async-openai).
     /// The total length of input tokens and generated tokens is limited by the
     /// model's context size.
     /// Splits will have at least that many tokens
     /// available for chat completion,
     /// never less.
-    max_tokens: u16,
+    max_tokens: u32,
+
+    /// The threshold below which `max_tokens` logs a warning.
+    recommended_min_tokens: u32,
+
+    /// The maximum number of input tokens to have in the chat,
+    /// if any.
+    ///
+    /// Splits will have at most that many input tokens,
+    /// never more.
+    max_input_tokens: Option<u32>,
+
+    /// The maximum number of input and generated tokens together,
+    /// if less than the context size.
+    combined_ceiling: Option<usize>,
+
+    /// The number of input tokens taken by something other than messages,
+    /// e.g.,
+    /// tool definitions.
+    reserved_tokens: usize,
+
+    /// The number of input tokens left unused,
+    /// in case token counts are off.
+    safety_margin_tokens: u32,
+
+    /// The number of messages the caller prepends after splitting,
+    /// and
+    /// the input tokens they take.
+    prefix: (usize, usize),
 
     /// The maximum number of messages to have in the chat.
     ///
     /// Splits will have at most that many messages,
     /// never more.
     max_messages: usize,
+
+    /// The hard limit on the number of messages imposed by the API.
+    message_limit: usize,
+
+    /// The input token budget for a single split,
+    /// overriding every other token limit.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    input_budget: Option<usize>,
+
+    /// The minimum number of messages to have in the chat.
+    ///
+    /// Splits will have at least that many messages,
+    /// even if they exceed the token limits.
+    min_messages: usize,
+
+    /// The limits to consider when splitting.
+    strategy: SplitStrategy,
+
+    /// How much the limits may be exceeded.
+    soft_limits: SoftLimits,
+
+    /// The estimated number of tokens each image in a message takes.
+    image_tokens: usize,
+
+    /// Whether messages that cannot be tokenized exactly are an error.
+    strict: bool,
+
+    /// The number of tokens the token limits are exceeded by before
+    /// trimming,
+    /// and
+    /// undercut by after trimming.
+    hysteresis_tokens: usize,
+
+    /// Where messages are dropped from.
+    trim_mode: TrimMode,
+
+    /// The number of leading messages kept when trimming the middle.
+    head_messages: usize,
+
+    /// The number of leading messages that are never 'outdated'.
+    keep_first: usize,
+
+    /// The per-message and per-reply token overhead,
+    /// if not using the one of [`tiktoken_rs`].
+    token_overhead: Option<(usize, usize)>,
+
+    /// Whether a leading system prompt is never considered 'outdated'.
+    pin_system_prompt: bool,
+
+    /// Whether system messages identical to a prepended system prompt are
+    /// dropped.
+    dedupe_system: bool,
+
+    /// Whether the last message is never considered 'outdated'.
+    pin_last: bool,
+
+    /// Whether 'recent' messages never start with an orphaned `assistant`
+    /// message.
+    keep_pairs: bool,
+
+    /// Whether 'recent' messages never start with an orphaned `tool` message.
+    keep_tool_groups: bool,
+
+    /// Whether a last message too large to ever fit is truncated.
+    truncate_oversized: bool,
+
+    /// Whether consecutive messages of the same role are counted as one.
+    coalesce_consecutive: bool,
+
+    /// The number of tokens between each two adjacent messages,
+    /// on top of the per-message overhead.
+    message_separator_tokens: usize,
+
+    /// Whether 'recent' messages start at a turn boundary.
+    snap_to_turns: bool,
+
+    /// A custom tokenizer,
+    /// if not using the default one based on [`tiktoken_rs`].
+    ///
+    /// It cannot be serialized,
+    /// so it has to be set again after deserialization.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    tokenizer: Option<SharedTokenizer>,
+
+    /// Whether the context size is still the one of `model`,
+    /// even with a custom tokenizer,
+    /// as with an estimator.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    estimated: bool,
+
+    /// A callback observing every probe of the token limit,
+    /// if any.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    on_probe: Option<ProbeHook>,
+
+    /// The tokenizer of the model for the default tokenizer,
+    /// built on first use and shared by clones.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    bpe: BpeCache,
+}
+
+/// Lazily built tokenizer of a model
+/// (see [`ChatSplitter::bpe`]).
+///
+/// It is `None` if the model is not a supported chat model.
+#[derive(Clone, Default)]
+struct BpeCache(Arc<OnceLock<Option<CoreBPE>>>);
+
+impl fmt::Debug for BpeCache {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BpeCache")
+            .field("initialized", &self.0.get().is_some())
+            .finish()
+    }
+}
+
+/// A cache is not part of the configuration,
+/// so all caches are equal.
+impl PartialEq for BpeCache {
+    #[inline]
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for BpeCache {}
+
+impl Hash for BpeCache {
+    #[inline]
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
+
+/// Custom tokenizer shared by clones of a splitter
+/// (see [`ChatSplitter::with_tokenizer`]).
+///
+/// Tokenizers are only equal if they are the same instance.
+#[derive(Clone, Debug)]
+struct SharedTokenizer(Arc<dyn Tokenizer>);
+
+impl PartialEq for SharedTokenizer {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for SharedTokenizer {}
+
+impl Hash for SharedTokenizer {
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.0).cast::<()>().hash(state);
+    }
+}
+
+/// Callback observing the suffixes probed when searching for a split
+/// position
+/// (see [`ChatSplitter::on_probe`]).
+///
+/// Callbacks are only equal if they are the same instance.
+#[derive(Clone)]
+struct ProbeHook(Arc<dyn Fn(usize, usize) + Send + Sync>);
+
+impl fmt::Debug for ProbeHook {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProbeHook").finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for ProbeHook {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for ProbeHook {}
+
+impl Hash for ProbeHook {
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.0).cast::<()>().hash(state);
+    }
+}
+
+/// Error that can happen when splitting a chat.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SplitError {
+    /// The model is not known to [`tiktoken_rs`],
+    /// or is not a supported chat model.
+    UnknownModel(String),
+
+    /// A message has a role that cannot be used for tokenization.
+    UnsupportedRole(String),
+
+    /// The tokens left for chat completion take the whole context,
+    /// so that no input can ever fit.
+    CompletionBudgetTooLarge {
+        /// The maximum number of tokens left for chat completion.
+        max_tokens: usize,
+
+        /// The context size of the model,
+        /// or the combined ceiling if smaller
+        /// (see [`ChatSplitter::combined_ceiling`]).
+        context_size: usize,
+    },
+
+    /// The maximum number of messages exceeds the hard message limit.
+    MaxMessagesTooLarge {
+        /// The maximum number of messages to have in the chat.
+        max_messages: usize,
+
+        /// The hard limit on the number of messages.
+        message_limit: usize,
+    },
+
+    /// The minimum number of messages exceeds the maximum one.
+    MinMessagesTooLarge {
+        /// The minimum number of messages to have in the chat.
+        min_messages: usize,
+
+        /// The maximum number of messages to have in the chat.
+        max_messages: usize,
+    },
+
+    /// A message cannot be tokenized without losing data,
+    /// e.g.,
+    /// its images,
+    /// so its tokens are only estimated
+    /// (see [`ChatSplitter::strict`]).
+    LossyConversion {
+        /// The index of the message in the chat.
+        index: usize,
+    },
+}
+
+impl fmt::Display for SplitError {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownModel(model) => write!(f, "unknown model '{model}'"),
+            Self::UnsupportedRole(role) => write!(f, "unsupported role '{role}'"),
+            Self::CompletionBudgetTooLarge {
+                max_tokens,
+                context_size,
+            } => write!(
+                f,
+                "max_tokens = {max_tokens} leaves no room for input in a context of {context_size} tokens"
+            ),
+            Self::MaxMessagesTooLarge {
+                max_messages,
+                message_limit,
+            } => write!(f, "max_messages = {max_messages} > {message_limit}"),
+            Self::MinMessagesTooLarge {
+                min_messages,
+                max_messages,
+            } => write!(f, "min_messages = {min_messages} > {max_messages}"),
+            Self::LossyConversion { index } => {
+                write!(f, "message {index} cannot be tokenized without losing data")
+            }
+        }
+    }
+}
+
+impl Error for SplitError {}
+
+impl From<ConversionError> for SplitError {
+    #[inline]
+    fn from(error: ConversionError) -> Self {
+        match error {
+            ConversionError::UnsupportedRole(role) => Self::UnsupportedRole(role),
+            ConversionError::InvalidMessage { error, .. } => (*error).into(),
+        }
+    }
+}
+
+/// Error that can happen when converting between chat completion request
+/// message types.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConversionError {
+    /// The message has a role that cannot be converted.
+    UnsupportedRole(String),
+
+    /// Some message in a chat cannot be converted,
+    /// as reported by [`ChatSplitter::validate_messages`].
+    InvalidMessage {
+        /// The index of the message in the chat.
+        index: usize,
+
+        /// The reason why the message cannot be converted.
+        error: Box<ConversionError>,
+    },
+}
+
+impl fmt::Display for ConversionError {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedRole(role) => write!(f, "unsupported role '{role}'"),
+            Self::InvalidMessage { index, error } => write!(f, "message {index}: {error}"),
+        }
+    }
+}
+
+impl Error for ConversionError {
+    #[inline]
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::UnsupportedRole(_) => None,
+            Self::InvalidMessage { error, .. } => Some(error),
+        }
+    }
 }
 
-/// Hard limit that seems to be imposed by the `OpenAI` API.
+/// Summary of a split,
+/// as returned by [`ChatSplitter::try_split_summary`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SplitSummary {
+    /// The split position,
+    /// as returned by [`ChatSplitter::try_split_position`].
+    pub position: usize,
+
+    /// The number of input tokens the 'outdated' messages would take.
+    pub outdated_tokens: usize,
+
+    /// The number of input tokens the 'recent' messages take,
+    /// including a pinned system prompt, if any.
+    pub recent_tokens: usize,
+}
+
+/// Statistics about a split,
+/// as returned by [`ChatSplitter::try_split_with_stats`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SplitStats {
+    /// The number of 'outdated' messages.
+    pub outdated_messages: usize,
+
+    /// The number of 'recent' messages,
+    /// including a pinned system prompt, if any.
+    pub recent_messages: usize,
+
+    /// The number of input tokens the 'outdated' messages would take.
+    pub outdated_tokens: usize,
+
+    /// The number of input tokens the 'recent' messages take,
+    /// including a pinned system prompt, if any.
+    pub recent_tokens: usize,
+
+    /// The limit that determined the split.
+    pub binding_constraint: BindingConstraint,
+}
+
+/// Limit that determined a split,
+/// as reported by [`SplitPlan::binding_constraint`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BindingConstraint {
+    /// The whole chat fits both limits.
+    None,
+
+    /// The message limit alone trimmed enough messages
+    /// (see [`ChatSplitter::max_messages`]),
+    /// so raising it would keep more messages.
+    Messages,
+
+    /// The token limit alone trimmed messages
+    /// (see [`ChatSplitter::max_tokens`]),
+    /// so shortening messages would keep more of them.
+    Tokens,
+
+    /// The message limit trimmed messages,
+    /// and
+    /// the token limit then trimmed even more.
+    Both,
+}
+
+/// Plan of a split,
+/// as returned by [`ChatSplitter::try_plan`].
+///
+/// Unlike the split itself,
+/// it does not borrow the messages,
+/// so it can be applied later
+/// (see [`SplitPlan::split`]).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SplitPlan {
+    /// The split position,
+    /// as returned by [`ChatSplitter::try_split_position`].
+    pub position: usize,
+
+    /// The number of leading messages that are never 'outdated'
+    /// (see [`ChatSplitter::pin_system_prompt`],
+    /// [`ChatSplitter::trim_mode`] and [`ChatSplitter::keep_first`]).
+    pub pinned_messages: usize,
+
+    /// The number of 'outdated' messages.
+    pub outdated_messages: usize,
+
+    /// The number of 'recent' messages,
+    /// excluding pinned ones.
+    pub recent_messages: usize,
+
+    /// Whether [`ChatSplitter::max_messages`] moved the boundary.
+    pub max_messages_hit: bool,
+
+    /// Whether [`ChatSplitter::max_tokens`] moved the boundary.
+    pub max_tokens_hit: bool,
+
+    /// Whether the boundary was moved to keep pairs or tool groups together
+    /// (see [`ChatSplitter::keep_pairs`] and [`ChatSplitter::keep_tool_groups`]).
+    pub kept_pairs: bool,
+
+    /// Whether the boundary was moved to start at a turn
+    /// (see [`ChatSplitter::snap_to_turns`]).
+    pub snapped_to_turn: bool,
+
+    /// Whether [`ChatSplitter::min_messages`] or [`ChatSplitter::pin_last`]
+    /// moved the boundary back,
+    /// overriding the token limits.
+    pub min_messages_hit: bool,
+}
+
+impl SplitPlan {
+    /// Get the index range of the 'outdated' messages.
+    ///
+    /// Everything outside of it is 'recent',
+    /// as in [`ChatSplitter::try_split_ranges`].
+    #[inline]
+    pub fn outdated(&self) -> Range<usize> {
+        self.pinned_messages..self.position
+    }
+
+    /// Split the chat into two groups of messages,
+    /// the 'outdated' and the
+    /// 'recent' ones,
+    /// exactly as [`ChatSplitter::try_split`] does.
+    ///
+    /// # Panics
+    ///
+    /// If there are fewer messages than when planning.
+    #[inline]
+    pub fn split<'a, M>(&self, messages: &'a [M]) -> (&'a [M], &'a [M]) {
+        (&messages[self.outdated()], &messages[self.position..])
+    }
+
+    /// Get the limit that determined the split.
+    #[inline]
+    pub fn binding_constraint(&self) -> BindingConstraint {
+        match (self.max_messages_hit, self.max_tokens_hit) {
+            (false, false) => BindingConstraint::None,
+            (true, false) => BindingConstraint::Messages,
+            (false, true) => BindingConstraint::Tokens,
+            (true, true) => BindingConstraint::Both,
+        }
+    }
+}
+
+/// Limits to consider when splitting a chat,
+/// as set by [`ChatSplitter::strategy`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SplitStrategy {
+    /// Only consider the message limits,
+    /// without ever tokenizing.
+    MessagesOnly,
+
+    /// Only consider the token limits,
+    /// besides the hard limit on the number of messages imposed by the API.
+    TokensOnly,
+
+    /// Consider both the message and the token limits.
+    #[default]
+    Both,
+}
+
+impl SplitStrategy {
+    /// Check whether [`ChatSplitter::max_messages`] is considered.
+    #[inline]
+    const fn uses_messages(self) -> bool {
+        matches!(self, Self::MessagesOnly | Self::Both)
+    }
+
+    /// Check whether [`ChatSplitter::max_tokens`] is considered.
+    #[inline]
+    const fn uses_tokens(self) -> bool {
+        matches!(self, Self::TokensOnly | Self::Both)
+    }
+}
+
+/// Where messages are dropped from when splitting a chat,
+/// as set by [`ChatSplitter::trim_mode`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TrimMode {
+    /// Drop the earliest messages.
+    #[default]
+    Front,
+
+    /// Keep the earliest [`ChatSplitter::head_messages`] messages
+    /// and drop the ones right after them.
+    Middle,
+}
+
+/// How much 'recent' messages may exceed the limits,
+/// as set by [`ChatSplitter::soft_limits`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SoftLimits {
+    /// The number of messages allowed over [`ChatSplitter::max_messages`].
+    pub message_slack: usize,
+
+    /// The number of input tokens allowed over the token limits.
+    pub token_slack: usize,
+}
+
+/// Default hard limit that seems to be imposed by the `OpenAI` API.
 const MAX_MESSAGES_LIMIT: usize = 2_048;
 
+/// Default estimate of the tokens an image takes,
+/// which is the cost of a high detail 1024x1024 image.
+const DEFAULT_IMAGE_TOKENS: usize = 765;
+
 /// Recommended minimum for maximum chat completion tokens.
-const RECOMMENDED_MIN_MAX_TOKENS: u16 = 256;
+const RECOMMENDED_MIN_MAX_TOKENS: u32 = 256;
 
 impl Default for ChatSplitter {
     #[inline]
@@ -93,27 +716,96 @@ impl Default for ChatSplitter {
 }
 
 impl ChatSplitter {
+    /// Create a new [`ChatSplitterBuilder`] with the default settings,
+    /// which validates all of them at once.
+    #[inline]
+    pub fn builder() -> ChatSplitterBuilder {
+        ChatSplitterBuilder::default()
+    }
+
     /// Create a new [`ChatSplitter`] for the given model.
-    ///
-    /// # Panics
-    ///
-    /// If for some reason [`tiktoken_rs`] gives a context size twice as large
-    /// as what would fit in a [`u16`].
-    /// If this happens,
-    /// it should be considered a bug,
-    /// but this behaviour might change in the future,
-    /// as models with larger context sizes are released.
     #[inline]
     pub fn new(model: impl Into<String>) -> Self {
         let model = model.into();
-        let max_tokens = u16::try_from(get_context_size(&model) / 2).unwrap();
+        warn_implausible_context_size(&model);
+        let max_tokens = u32::try_from(context_size(&model) / 2).unwrap_or(u32::MAX);
 
         let max_messages = MAX_MESSAGES_LIMIT / 2;
 
         Self {
             model,
+            tokenizer_model: None,
             max_tokens,
+            recommended_min_tokens: RECOMMENDED_MIN_MAX_TOKENS,
+            max_input_tokens: None,
+            combined_ceiling: None,
+            reserved_tokens: 0,
+            safety_margin_tokens: 0,
+            prefix: (0, 0),
+            input_budget: None,
             max_messages,
+            message_limit: MAX_MESSAGES_LIMIT,
+            min_messages: 0,
+            strategy: SplitStrategy::Both,
+            soft_limits: SoftLimits {
+                message_slack: 0,
+                token_slack: 0,
+            },
+            image_tokens: DEFAULT_IMAGE_TOKENS,
+            strict: false,
+            hysteresis_tokens: 0,
+            trim_mode: TrimMode::Front,
+            head_messages: 1,
+            keep_first: 0,
+            token_overhead: None,
+            pin_system_prompt: false,
+            dedupe_system: false,
+            pin_last: false,
+            keep_pairs: false,
+            keep_tool_groups: false,
+            truncate_oversized: false,
+            coalesce_consecutive: false,
+            message_separator_tokens: 0,
+            snap_to_turns: false,
+            tokenizer: None,
+            estimated: false,
+            on_probe: None,
+            bpe: BpeCache::default(),
+        }
+    }
+
+    /// Create a new [`ChatSplitter`] for the given model,
+    /// leaving the given fraction of its context size for chat completion.
+    ///
+    /// The fraction should be between zero and one,
+    /// otherwise a warning is logged.
+    /// [`ChatSplitter::new`] leaves half of the context size,
+    /// i.e.,
+    /// uses a ratio of `0.5`.
+    #[inline]
+    pub fn with_ratio(model: impl Into<String>, ratio: f64) -> Self {
+        if !(ratio > 0.0 && ratio < 1.0) {
+            log::warn!("ratio = {ratio} not in (0, 1)");
+        }
+        let splitter = Self::new(model);
+        // Casting saturates,
+        // so that any ratio gives a valid number of tokens.
+        let max_tokens = (splitter.context_size() as f64 * ratio) as u32;
+        splitter.max_tokens(max_tokens)
+    }
+
+    /// Create a new [`ChatSplitter`] for the model and completion budget of
+    /// the given request.
+    ///
+    /// If the request has no `max_tokens`,
+    /// the default of [`ChatSplitter::new`] is kept.
+    #[cfg(feature = "async-openai")]
+    #[inline]
+    pub fn from_request(request: &async_openai::types::CreateChatCompletionRequest) -> Self {
+        let splitter = Self::new(&request.model);
+        match request.max_tokens {
+            Some(max_tokens) => splitter.max_tokens(max_tokens),
+            None => splitter,
         }
     }
 
@@ -125,18 +817,54 @@ impl ChatSplitter {
     #[must_use]
     pub fn max_messages(mut self, max_messages: impl Into<usize>) -> Self {
         self.max_messages = max_messages.into();
-        if self.max_messages > MAX_MESSAGES_LIMIT {
+        if self.max_messages > self.message_limit {
             log::warn!(
-                "max_messages = {} > {MAX_MESSAGES_LIMIT}",
-                self.max_messages
+                "max_messages = {} > {}",
+                self.max_messages,
+                self.message_limit
             );
         }
         self
     }
 
+    /// Set the hard limit on the number of messages imposed by the API.
+    ///
+    /// Splits will never have more messages than this,
+    /// regardless of [`ChatSplitter::max_messages`].
+    /// Defaults to 2048,
+    /// which seems to be the limit imposed by the `OpenAI` API,
+    /// but compatible backends might allow more or less.
+    #[inline]
+    #[must_use]
+    pub fn message_limit(mut self, message_limit: impl Into<usize>) -> Self {
+        self.message_limit = message_limit.into();
+        self
+    }
+
+    /// Set the minimum number of messages to have in the chat.
+    ///
+    /// Splits will have at least that many messages
+    /// (if available),
+    /// even if they take more tokens than allowed by
+    /// [`ChatSplitter::max_tokens`]
+    /// or
+    /// [`ChatSplitter::max_input_tokens`],
+    /// in which case a warning is logged.
+    /// This takes precedence over [`ChatSplitter::keep_pairs`]
+    /// and
+    /// [`ChatSplitter::keep_tool_groups`] as well,
+    /// but never over [`ChatSplitter::max_messages`].
+    /// Defaults to zero.
+    #[inline]
+    #[must_use]
+    pub fn min_messages(mut self, min_messages: impl Into<usize>) -> Self {
+        self.min_messages = min_messages.into();
+        self
+    }
+
     /// Set the maximum number of tokens to leave for chat completion.
     ///
-    /// This is the same as in the [official API](https://platform.openai.com/docs/api-reference/chat#completions/create-prompt) and given to [`async_openai`].
+    /// This is the same as in the [official API](https://platform.openai.com/docs/api-reference/chat#completions/create-prompt) and given to [`async_openai`](https://github.com/64bit/async-openai).
     /// The total length of input tokens and generated tokens is limited by the
     /// model's context size.
     /// Splits will have at least that many tokens
@@ -144,368 +872,5718 @@ impl ChatSplitter {
     /// never less.
     #[inline]
     #[must_use]
-    pub fn max_tokens(mut self, max_tokens: impl Into<u16>) -> Self {
+    pub fn max_tokens(mut self, max_tokens: impl Into<u32>) -> Self {
         self.max_tokens = max_tokens.into();
-        if self.max_tokens < RECOMMENDED_MIN_MAX_TOKENS {
+        if self.max_tokens < self.recommended_min_tokens {
             log::warn!(
-                "max_tokens = {} < {RECOMMENDED_MIN_MAX_TOKENS}",
-                self.max_tokens
+                "max_tokens = {} < {}",
+                self.max_tokens,
+                self.recommended_min_tokens
             );
         }
         self
     }
 
-    /// Set the model to use for tokenization,
-    /// e.g.,
-    /// `gpt-3.5-turbo`.
+    /// Set the threshold below which [`ChatSplitter::max_tokens`] logs a
+    /// warning.
     ///
-    /// It is passed to [`tiktoken_rs`] to select the correct tokenizer.
+    /// This only affects later calls to [`ChatSplitter::max_tokens`],
+    /// so it should be set first.
+    /// Set it to zero to silence the warning entirely.
+    /// Defaults to `256`.
     #[inline]
     #[must_use]
-    pub fn model(mut self, model: impl Into<String>) -> Self {
-        self.model = model.into();
+    pub fn recommended_min_tokens(mut self, recommended_min_tokens: impl Into<u32>) -> Self {
+        self.recommended_min_tokens = recommended_min_tokens.into();
         self
     }
 
-    /// Get a split position by only considering `max_messages`.
+    /// Set the maximum number of input tokens to have in the chat.
+    ///
+    /// This is an alternative to [`ChatSplitter::max_tokens`]:
+    /// splits will have at most that many input tokens,
+    /// never more.
+    /// If both are set,
+    /// the tighter one wins,
+    /// i.e.,
+    /// 'recent' messages take at most
+    /// `min(max_input_tokens, context_size - max_tokens)` tokens.
     #[inline]
-    fn position_by_max_messages<M>(&self, messages: &[M]) -> usize {
-        let upper_limit = self.max_messages.min(MAX_MESSAGES_LIMIT);
+    #[must_use]
+    pub fn max_input_tokens(mut self, max_input_tokens: impl Into<u32>) -> Self {
+        self.max_input_tokens = Some(max_input_tokens.into());
+        self
+    }
 
-        let n = messages.len();
-        let n = if n <= upper_limit { 0 } else { n - upper_limit };
-        debug_assert!(messages[n..].len() <= upper_limit);
-        n
+    /// Reserve a fixed number of input tokens for something other than
+    /// messages,
+    /// e.g.,
+    /// `tools` or `functions` definitions.
+    ///
+    /// These tokens are taken from the input token limit
+    /// (see [`ChatSplitter::max_tokens`] and [`ChatSplitter::max_input_tokens`])
+    /// before splitting,
+    /// so 'recent' messages take that many tokens less.
+    /// Computing them is up to the caller,
+    /// e.g.,
+    /// by tokenizing the serialized definitions.
+    /// Defaults to zero.
+    #[inline]
+    #[must_use]
+    pub fn reserve_tokens(mut self, reserved_tokens: impl Into<usize>) -> Self {
+        self.reserved_tokens = reserved_tokens.into();
+        self
     }
 
-    /// Get a split position by only considering `max_tokens`.
+    /// Reserve room for messages prepended after splitting.
+    ///
+    /// This is the same as [`ChatSplitter::try_reserve_prefix`],
+    /// but panics instead of returning an error.
     ///
     /// # Panics
     ///
-    /// If tokenizer for the specified model is not found or is not a supported
-    /// chat model.
+    /// If [`ChatSplitter::try_reserve_prefix`] fails.
     #[inline]
-    fn position_by_max_tokens<M>(&self, messages: &[M]) -> usize
+    #[must_use]
+    pub fn reserve_prefix<M>(self, prefix: &[M]) -> Self
     where
         M: IntoChatCompletionRequestMessage + Clone,
     {
-        let max_tokens = self.max_tokens as usize;
-        let lower_limit = max_tokens.min(get_context_size(&self.model));
-
-        let messages: Vec<_> = messages
-            .iter()
-            .cloned()
-            .map(IntoChatCompletionRequestMessage::into_tiktoken_rs)
-            .collect();
-
-        let (n, _range) = (0..=messages.len()).binary_any(|n| {
-            debug_assert!(n < messages.len());
-
-            let tokens = get_chat_completion_max_tokens(&self.model, &messages[n..])
-                .expect("tokenizer should be available");
-
-            let cmp = tokens.cmp(&lower_limit);
-            debug_assert_ne!(cmp, Ordering::Equal);
-            cmp
-        });
-
-        debug_assert!(
-            get_chat_completion_max_tokens(&self.model, &messages[n..])
-                .expect("tokenizer should be available")
-                >= lower_limit
-        );
-        n
+        self.try_reserve_prefix(prefix).unwrap()
     }
 
-    /// Get a split position by first considering the `max_messages` limit,
-    /// then
-    /// the `max_tokens` limit.
+    /// Reserve room for messages prepended after splitting,
+    /// e.g.,
+    /// a fresh system prompt,
+    /// as in
+    /// [`examples/chat.rs`](https://github.com/schneiderfelipe/chat-splitter/blob/main/examples/chat.rs).
     ///
-    /// # Panics
+    /// Their messages and tokens are taken from the limits before splitting,
+    /// so that the actual request,
+    /// with the prefix,
+    /// still satisfies them.
+    /// They are counted with the current configuration,
+    /// so this should be set last.
+    /// Calling it again replaces the previous prefix.
+    ///
+    /// # Errors
     ///
     /// If tokenizer for the specified model is not found or is not a supported
-    /// chat model.
+    /// chat model
+    /// ([`SplitError::UnknownModel`]),
+    /// or if some message cannot be converted for tokenization
+    /// ([`SplitError::UnsupportedRole`]).
     #[inline]
-    fn position<M>(&self, messages: &[M]) -> usize
+    pub fn try_reserve_prefix<M>(mut self, prefix: &[M]) -> Result<Self, SplitError>
     where
         M: IntoChatCompletionRequestMessage + Clone,
     {
-        let n = self.position_by_max_messages(messages);
-        n + self.position_by_max_tokens(&messages[n..])
+        let tokens = self.message_tokens(prefix)?.into_iter().sum();
+        self.prefix = (prefix.len(), tokens);
+        Ok(self)
     }
 
-    /// Split the chat into two groups of messages,
-    /// the 'outdated' and the
-    /// 'recent' ones.
+    /// Set a number of input tokens to always leave unused.
     ///
-    /// The 'recent' messages are guaranteed to satisfy the given limits,
+    /// Token counts might be slightly off from the ones of the API,
+    /// e.g.,
+    /// when it changes its message format,
+    /// so this makes sure requests never go over the limits by a few tokens.
+    /// It is taken from the input token limit,
+    /// just like [`ChatSplitter::reserve_tokens`].
+    /// A few dozen tokens,
+    /// e.g.,
+    /// `32`,
+    /// are usually enough.
+    /// Defaults to zero.
+    #[inline]
+    #[must_use]
+    pub fn safety_margin_tokens(mut self, safety_margin_tokens: impl Into<u32>) -> Self {
+        self.safety_margin_tokens = safety_margin_tokens.into();
+        self
+    }
+
+    /// Set the maximum number of input and generated tokens together.
+    ///
+    /// This is like using a model with a smaller context size,
+    /// e.g.,
+    /// to save costs:
+    /// 'recent' messages take at most
+    /// `combined_ceiling - max_tokens` tokens.
+    /// A ceiling larger than the context size has no effect.
+    #[inline]
+    #[must_use]
+    pub fn combined_ceiling(mut self, combined_ceiling: impl Into<usize>) -> Self {
+        let combined_ceiling = combined_ceiling.into();
+        let context_size = self.context_size();
+        if combined_ceiling > context_size {
+            log::warn!("combined_ceiling = {combined_ceiling} > {context_size}");
+        }
+        self.combined_ceiling = Some(combined_ceiling);
+        self
+    }
+
+    /// Get the maximum number of input and generated tokens together,
+    /// i.e.,
+    /// the context size,
+    /// unless a smaller combined ceiling is set.
+    #[inline]
+    fn combined_size(&self) -> usize {
+        let context_size = self.context_size();
+        self.combined_ceiling
+            .map_or(context_size, |combined_ceiling| {
+                combined_ceiling.min(context_size)
+            })
+    }
+
+    /// Reserve a fixed number of tokens for chat completion,
+    /// letting the input take the rest of the model's context size.
+    ///
+    /// This sets [`ChatSplitter::max_tokens`] to `reserved`,
+    /// so that 'recent' messages fill at most
+    /// `context_size - reserved` tokens.
+    #[inline]
+    #[must_use]
+    pub fn reserve_completion_tokens(self, reserved: impl Into<u32>) -> Self {
+        let reserved = reserved.into();
+        let context_size = self.context_size();
+        if reserved as usize >= context_size {
+            log::warn!("reserved = {reserved} >= {context_size}");
+        }
+        self.max_tokens(reserved)
+    }
+
+    /// Set the model to use for tokenization,
+    /// e.g.,
+    /// `gpt-3.5-turbo`.
+    ///
+    /// It is passed to [`tiktoken_rs`] to select the correct tokenizer.
+    #[inline]
+    #[must_use]
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        warn_implausible_context_size(&self.model);
+        self.bpe = BpeCache::default();
+        self
+    }
+
+    /// Set the model to use for tokenization only,
+    /// e.g.,
+    /// `gpt-4`,
+    /// if different from [`ChatSplitter::model`].
+    ///
+    /// This is useful for model aliases that [`tiktoken_rs`] does not know
+    /// about,
+    /// but that tokenize exactly as some known model.
+    /// The context size is still the one of [`ChatSplitter::model`],
+    /// so it might have to be given with [`ChatSplitter::max_input_tokens`]
+    /// as well.
+    /// Defaults to [`ChatSplitter::model`].
+    #[inline]
+    #[must_use]
+    pub fn tokenizer_model(mut self, tokenizer_model: impl Into<String>) -> Self {
+        self.tokenizer_model = Some(tokenizer_model.into());
+        self.bpe = BpeCache::default();
+        self
+    }
+
+    /// Get the model to use for tokenization.
+    #[inline]
+    fn counting_model(&self) -> &str {
+        self.tokenizer_model.as_deref().unwrap_or(&self.model)
+    }
+
+    /// Use a custom [`Tokenizer`] for token counting,
+    /// instead of the default one based on [`tiktoken_rs`].
+    ///
+    /// The tokenizer also determines the context size,
+    /// and
+    /// takes precedence over [`ChatSplitter::model`] for both.
+    #[inline]
+    #[must_use]
+    pub fn with_tokenizer(mut self, tokenizer: impl Tokenizer + 'static) -> Self {
+        self.tokenizer = Some(SharedTokenizer(Arc::new(tokenizer)));
+        self.estimated = false;
+        self
+    }
+
+    /// Use an approximate token estimator instead of a tokenizer,
+    /// e.g.,
+    /// [`estimate_tokens`].
+    ///
+    /// The estimator gives the number of tokens each message takes,
+    /// including any per-message overhead,
     /// while
-    /// the 'outdated' ones contain all the ones before 'recent'.
+    /// the context size is still the one of [`ChatSplitter::model`],
+    /// even if set afterwards.
+    /// This avoids tokenization costs entirely,
+    /// but results are approximate,
+    /// so a safety margin on [`ChatSplitter::max_tokens`] is recommended.
+    /// Messages are still converted to [`tiktoken_rs`] messages,
+    /// which is why it is a required dependency.
+    #[inline]
+    #[must_use]
+    pub fn with_estimator<F>(self, estimator: F) -> Self
+    where
+        F: Fn(&tiktoken_rs::ChatCompletionRequestMessage) -> usize + Send + Sync + 'static,
+    {
+        let mut splitter = self.with_tokenizer(Estimator { estimator });
+        splitter.estimated = true;
+        splitter
+    }
+
+    /// Set a callback observing every probe of the token limit,
+    /// e.g.,
+    /// for debugging unexpected splits.
     ///
-    /// For a detailed usage example,
-    /// see [`examples/chat.rs`](https://github.com/schneiderfelipe/chat-splitter/blob/main/examples/chat.rs).
+    /// While searching for the split position,
+    /// the callback is called with the start of each probed suffix
+    /// and
+    /// the input tokens it takes,
+    /// including the reply and pinned messages.
+    /// The start is relative to the messages left by the message limits,
+    /// i.e.,
+    /// after pinned messages
+    /// and
+    /// those 'outdated' by [`ChatSplitter::max_messages`].
+    /// The callback has to be shareable,
+    /// so state is best kept behind a [`Mutex`](std::sync::Mutex).
+    /// Without a callback,
+    /// there is no overhead.
+    #[inline]
+    #[must_use]
+    pub fn on_probe<F>(mut self, on_probe: F) -> Self
+    where
+        F: Fn(usize, usize) + Send + Sync + 'static,
+    {
+        self.on_probe = Some(ProbeHook(Arc::new(on_probe)));
+        self
+    }
+
+    /// Check that every message can be converted for tokenization
+    /// and,
+    /// with the `async-openai` feature,
+    /// to [`async_openai`](https://github.com/64bit/async-openai) messages,
+    /// which includes parsing their roles.
+    ///
+    /// These are the conversions splitting
+    /// and
+    /// helpers such as `ChatSplitter::try_recent_async_openai` do,
+    /// so this is useful to fail fast with a clean error,
+    /// before any splitting.
+    ///
+    /// # Errors
+    ///
+    /// If some message cannot be converted
+    /// ([`ConversionError::InvalidMessage`]),
+    /// with the index of the first such message.
+    #[inline]
+    pub fn validate_messages<M>(&self, messages: &[M]) -> Result<(), ConversionError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        for (index, message) in messages.iter().enumerate() {
+            let invalid = |error| ConversionError::InvalidMessage {
+                index,
+                error: Box::new(error),
+            };
+            message.clone().try_into_tiktoken_rs().map_err(invalid)?;
+            #[cfg(feature = "async-openai")]
+            message.clone().try_into_async_openai().map_err(invalid)?;
+        }
+        Ok(())
+    }
+
+    /// Count the input tokens the given messages take,
+    /// using the configured tokenizer.
+    ///
+    /// This uses the same token counting as [`ChatSplitter::try_split`],
+    /// so the two never disagree.
+    ///
+    /// # Errors
+    ///
+    /// If tokenizer for the specified model is not found or is not a supported
+    /// chat model
+    /// ([`SplitError::UnknownModel`]),
+    /// or if some message cannot be converted for tokenization
+    /// ([`SplitError::UnsupportedRole`]).
+    #[inline]
+    pub fn count_tokens<M>(&self, messages: &[M]) -> Result<usize, SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        Ok(
+            self.count_tiktoken_rs_tokens(&try_into_tiktoken_rs(messages)?)?
+                + self.count_image_tokens(messages)?,
+        )
+    }
+
+    /// Get how full the context is with the given messages.
+    ///
+    /// This is the same as [`ChatSplitter::try_utilization`],
+    /// but panics instead of returning an error.
     ///
     /// # Panics
     ///
+    /// If [`ChatSplitter::try_utilization`] fails.
+    #[inline]
+    pub fn utilization<M>(&self, messages: &[M]) -> f64
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        self.try_utilization(messages).unwrap()
+    }
+
+    /// Get how full the context is with the given messages.
+    ///
+    /// This is the ratio of the input tokens the messages take,
+    /// as given by [`ChatSplitter::count_tokens`],
+    /// to the context size,
+    /// clamped to `1.0`.
+    /// This is useful to act before the chat has to be split,
+    /// e.g.,
+    /// by summarizing it.
+    ///
+    /// # Errors
+    ///
     /// If tokenizer for the specified model is not found or is not a supported
-    /// chat model.
+    /// chat model
+    /// ([`SplitError::UnknownModel`]),
+    /// or if some message cannot be converted for tokenization
+    /// ([`SplitError::UnsupportedRole`]).
     #[inline]
-    pub fn split<'a, M>(&self, messages: &'a [M]) -> (&'a [M], &'a [M])
+    pub fn try_utilization<M>(&self, messages: &[M]) -> Result<f64, SplitError>
     where
         M: IntoChatCompletionRequestMessage + Clone,
     {
-        messages.split_at(self.position(messages))
+        let tokens = self.count_tokens(messages)? as f64;
+        let context_size = self.context_size() as f64;
+        Ok((tokens / context_size).min(1.0))
     }
-}
 
-/// Extension trait for converting between different chat completion request
-/// message types.
-///
-/// For a usage example,
-/// see [`examples/chat.rs`](https://github.com/schneiderfelipe/chat-splitter/blob/736f4fceb57bc12adb2b70deb990030a266a95a5/examples/chat.rs#L44-L55).
-pub trait IntoChatCompletionRequestMessage {
-    /// Convert to [`tiktoken_rs` chat completion request message
-    /// type](`tiktoken_rs::ChatCompletionRequestMessage`).
-    fn into_tiktoken_rs(self) -> tiktoken_rs::ChatCompletionRequestMessage;
-
-    /// Convert to [`async_openai` chat completion request message
-    /// type](`async_openai::types::ChatCompletionRequestMessage`).
-    fn into_async_openai(self) -> async_openai::types::ChatCompletionRequestMessage;
-}
+    /// Estimate the cost of sending the given messages.
+    ///
+    /// This is the same as [`ChatSplitter::try_estimate_cost`],
+    /// but panics instead of returning an error.
+    ///
+    /// # Panics
+    ///
+    /// If [`ChatSplitter::try_estimate_cost`] fails.
+    #[inline]
+    pub fn estimate_cost<M>(&self, messages: &[M], price_per_1k_input: f64) -> f64
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        self.try_estimate_cost(messages, price_per_1k_input)
+            .unwrap()
+    }
+
+    /// Estimate the cost of sending the given messages,
+    /// given the price per thousand input tokens.
+    ///
+    /// The input tokens are given by [`ChatSplitter::count_tokens`].
+    /// Prices are not part of this crate,
+    /// since they change often.
+    ///
+    /// # Errors
+    ///
+    /// If tokenizer for the specified model is not found or is not a supported
+    /// chat model
+    /// ([`SplitError::UnknownModel`]),
+    /// or if some message cannot be converted for tokenization
+    /// ([`SplitError::UnsupportedRole`]).
+    #[inline]
+    pub fn try_estimate_cost<M>(
+        &self,
+        messages: &[M],
+        price_per_1k_input: f64,
+    ) -> Result<f64, SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        let tokens = self.count_tokens(messages)? as f64;
+        Ok(tokens / 1000.0 * price_per_1k_input)
+    }
 
-impl IntoChatCompletionRequestMessage for tiktoken_rs::ChatCompletionRequestMessage {
+    /// Count the estimated tokens the images in the given messages take
+    /// (see [`ChatSplitter::image_tokens`]).
+    ///
+    /// # Errors
+    ///
+    /// If some message has images in strict mode
+    /// (see [`ChatSplitter::strict`]).
     #[inline]
-    fn into_tiktoken_rs(self) -> tiktoken_rs::ChatCompletionRequestMessage {
-        self
+    fn count_image_tokens<M>(&self, messages: &[M]) -> Result<usize, SplitError>
+    where
+        M: IntoChatCompletionRequestMessage,
+    {
+        messages
+            .iter()
+            .enumerate()
+            .map(|(index, message)| self.message_image_tokens(index, message))
+            .sum()
     }
 
+    /// Count the estimated tokens the images in the given message take,
+    /// given its index in the chat.
+    ///
+    /// # Errors
+    ///
+    /// If the message has images in strict mode
+    /// (see [`ChatSplitter::strict`]).
     #[inline]
-    fn into_async_openai(self) -> async_openai::types::ChatCompletionRequestMessage {
-        match self.role.as_ref() {
-            "user" => async_openai::types::ChatCompletionRequestMessage::User(
-                async_openai::types::ChatCompletionRequestUserMessage {
-                    role: match self.role.as_ref() {
-                        "user" => async_openai::types::Role::User,
-                        "system" => async_openai::types::Role::System,
-                        "assistant" => async_openai::types::Role::Assistant,
-                        "function" => async_openai::types::Role::Function,
-                        role => panic!("unsupported role '{role}'"),
-                    },
-                    content: match self.content {
-                        Some(text) => {
-                            async_openai::types::ChatCompletionRequestUserMessageContent::Text(text)
-                        }
-                        None => {
-                            async_openai::types::ChatCompletionRequestUserMessageContent::Array(
-                                Vec::new(),
-                            )
-                        }
-                    },
-                    name: self.name,
-                },
-            ),
-            "system" => async_openai::types::ChatCompletionRequestMessage::System(
-                async_openai::types::ChatCompletionRequestSystemMessage {
-                    role: match self.role.as_ref() {
-                        "user" => async_openai::types::Role::User,
-                        "system" => async_openai::types::Role::System,
-                        "assistant" => async_openai::types::Role::Assistant,
-                        "function" => async_openai::types::Role::Function,
-                        role => panic!("unsupported role '{role}'"),
-                    },
-                    content: self
-                        .content
-                        .expect("system message content should be valid"),
-                    name: self.name,
-                },
-            ),
-            "assistant" => async_openai::types::ChatCompletionRequestMessage::Assistant(
-                async_openai::types::ChatCompletionRequestAssistantMessage {
-                    role: match self.role.as_ref() {
-                        "user" => async_openai::types::Role::User,
-                        "system" => async_openai::types::Role::System,
-                        "assistant" => async_openai::types::Role::Assistant,
-                        "function" => async_openai::types::Role::Function,
-                        role => panic!("unsupported role '{role}'"),
-                    },
-                    content: self.content,
-                    tool_calls: None,
-                    function_call: self
-                        .function_call
-                        .map(|fc| async_openai::types::FunctionCall {
-                            name: fc.name,
-                            arguments: fc.arguments,
-                        }),
-                    name: self.name,
-                },
-            ),
-            "function" => async_openai::types::ChatCompletionRequestMessage::Function(
-                async_openai::types::ChatCompletionRequestFunctionMessage {
-                    role: match self.role.as_ref() {
-                        "user" => async_openai::types::Role::User,
-                        "system" => async_openai::types::Role::System,
-                        "assistant" => async_openai::types::Role::Assistant,
-                        "function" => async_openai::types::Role::Function,
-                        role => panic!("unsupported role '{role}'"),
-                    },
-                    content: self.content,
-                    name: self.name.expect("function message name should be valid"),
-                },
-            ),
-            role => panic!("unsupported role '{role}'"),
+    fn message_image_tokens<M>(&self, index: usize, message: &M) -> Result<usize, SplitError>
+    where
+        M: IntoChatCompletionRequestMessage,
+    {
+        match message.image_count() {
+            0 => Ok(0),
+            _ if self.strict => Err(SplitError::LossyConversion { index }),
+            images => Ok(images * self.image_tokens),
         }
     }
-}
 
-impl IntoChatCompletionRequestMessage for async_openai::types::ChatCompletionRequestMessage {
+    /// Count the input tokens the given converted messages take,
+    /// using the configured tokenizer.
+    ///
+    /// # Errors
+    ///
+    /// If tokenizer for the specified model is not found or is not a supported
+    /// chat model.
     #[inline]
-    fn into_tiktoken_rs(self) -> tiktoken_rs::ChatCompletionRequestMessage {
-        match self {
-            async_openai::types::ChatCompletionRequestMessage::User(message) => {
-                tiktoken_rs::ChatCompletionRequestMessage {
-                    role: message.role.to_string(),
-                    content: match message.content {
-                        async_openai::types::ChatCompletionRequestUserMessageContent::Text(
-                            text,
-                        ) => Some(text),
-                        async_openai::types::ChatCompletionRequestUserMessageContent::Array(
-                            array,
-                        ) => {
-                            if array.is_empty() {
-                                None
-                            } else {
-                                panic!("unsupported user message content {array:?}")
-                            }
-                        }
-                    },
-                    function_call: None,
-                    name: message.name,
-                }
-            }
-            async_openai::types::ChatCompletionRequestMessage::System(message) => {
-                tiktoken_rs::ChatCompletionRequestMessage {
-                    role: message.role.to_string(),
-                    content: Some(message.content),
-                    function_call: None,
-                    name: message.name,
-                }
-            }
-            async_openai::types::ChatCompletionRequestMessage::Assistant(message) => {
-                tiktoken_rs::ChatCompletionRequestMessage {
-                    role: message.role.to_string(),
-                    content: message.content,
-                    function_call: message.function_call.map(|fc| tiktoken_rs::FunctionCall {
-                        name: fc.name,
-                        arguments: fc.arguments,
-                    }),
-                    name: message.name,
-                }
-            }
-            async_openai::types::ChatCompletionRequestMessage::Function(message) => {
-                tiktoken_rs::ChatCompletionRequestMessage {
-                    role: message.role.to_string(),
-                    content: message.content,
-                    function_call: None,
-                    name: Some(message.name),
-                }
-            }
-            role @ async_openai::types::ChatCompletionRequestMessage::Tool(_) => {
-                panic!("unsupported role '{role:?}'")
-            }
+    fn count_tiktoken_rs_tokens(
+        &self,
+        messages: &[tiktoken_rs::ChatCompletionRequestMessage],
+    ) -> Result<usize, SplitError> {
+        if self.coalesce_consecutive {
+            let coalesced: Vec<_> = coalescing_runs(messages)
+                .into_iter()
+                .map(|run| coalesce(&messages[run]))
+                .collect();
+            return Ok(
+                self.using_tokenizer(|tokenizer| tokenizer.count_tokens(&coalesced))?
+                    + self.separator_tokens(coalesced.len()),
+            );
         }
+        Ok(
+            self.using_tokenizer(|tokenizer| tokenizer.count_tokens(messages))?
+                + self.separator_tokens(messages.len()),
+        )
     }
 
+    /// Count the separator tokens between the given number of messages
+    /// (see [`ChatSplitter::message_separator_tokens`]).
     #[inline]
-    fn into_async_openai(self) -> async_openai::types::ChatCompletionRequestMessage {
-        self
+    fn separator_tokens(&self, messages: usize) -> usize {
+        self.message_separator_tokens * messages.saturating_sub(1)
+    }
+
+    /// Count the tokens each of the given converted messages take,
+    /// using the configured tokenizer.
+    ///
+    /// # Errors
+    ///
+    /// If tokenizer for the specified model is not found or is not a supported
+    /// chat model.
+    #[inline]
+    fn count_tiktoken_rs_message_tokens(
+        &self,
+        messages: &[tiktoken_rs::ChatCompletionRequestMessage],
+    ) -> Result<Vec<usize>, SplitError> {
+        let tokens = self.using_tokenizer(|tokenizer| tokenizer.count_message_tokens(messages))?;
+        self.adjust_message_tokens(messages, tokens)
     }
-}
 
-impl IntoChatCompletionRequestMessage for async_openai::types::ChatCompletionResponseMessage {
+    /// Adjust the tokens each of the given converted messages take
+    /// for coalescing
+    /// (see [`ChatSplitter::coalesce_consecutive`])
+    /// and
+    /// separators
+    /// (see [`ChatSplitter::message_separator_tokens`]).
+    ///
+    /// # Errors
+    ///
+    /// If tokenizer for the specified model is not found or is not a supported
+    /// chat model.
     #[inline]
-    fn into_tiktoken_rs(self) -> tiktoken_rs::ChatCompletionRequestMessage {
-        tiktoken_rs::ChatCompletionRequestMessage {
-            role: self.role.to_string(),
-            content: self.content,
-            function_call: self.function_call.map(|fc| tiktoken_rs::FunctionCall {
-                name: fc.name,
-                arguments: fc.arguments,
-            }),
-            name: None,
+    fn adjust_message_tokens(
+        &self,
+        messages: &[tiktoken_rs::ChatCompletionRequestMessage],
+        tokens: Vec<usize>,
+    ) -> Result<Vec<usize>, SplitError> {
+        let mut tokens = self.coalesce_message_tokens(messages, tokens)?;
+        if self.message_separator_tokens == 0 {
+            return Ok(tokens);
         }
+        let starts: Vec<_> = if self.coalesce_consecutive {
+            coalescing_runs(messages)
+                .into_iter()
+                .map(|run| run.start)
+                .collect()
+        } else {
+            (0..messages.len()).collect()
+        };
+        for start in starts.into_iter().skip(1) {
+            tokens[start] += self.message_separator_tokens;
+        }
+        Ok(tokens)
     }
 
+    /// Spread the tokens saved by coalescing consecutive messages
+    /// (see [`ChatSplitter::coalesce_consecutive`])
+    /// over the tokens each of the given converted messages take.
+    ///
+    /// # Errors
+    ///
+    /// If tokenizer for the specified model is not found or is not a supported
+    /// chat model.
     #[inline]
-    fn into_async_openai(self) -> async_openai::types::ChatCompletionRequestMessage {
-        match self.role {
-            async_openai::types::Role::User => {
-                async_openai::types::ChatCompletionRequestMessage::User(
-                    async_openai::types::ChatCompletionRequestUserMessage {
-                        role: self.role,
-                        content: match self.content {
-                            Some(text) => {
-                                async_openai::types::ChatCompletionRequestUserMessageContent::Text(
-                                    text,
-                                )
-                            }
-                            None => {
-                                async_openai::types::ChatCompletionRequestUserMessageContent::Array(
-                                    Vec::new(),
-                                )
-                            }
-                        },
-                        name: None,
-                    },
-                )
-            }
-            async_openai::types::Role::System => {
-                async_openai::types::ChatCompletionRequestMessage::System(
-                    async_openai::types::ChatCompletionRequestSystemMessage {
-                        role: self.role,
-                        content: self
-                            .content
-                            .expect("system message content should be valid"),
-                        name: None,
-                    },
-                )
-            }
-            async_openai::types::Role::Assistant => {
-                async_openai::types::ChatCompletionRequestMessage::Assistant(
-                    async_openai::types::ChatCompletionRequestAssistantMessage {
-                        role: self.role,
-                        content: self.content,
-                        tool_calls: None,
-                        function_call: self.function_call,
-                        name: None,
-                    },
-                )
-            }
-            async_openai::types::Role::Function => {
-                async_openai::types::ChatCompletionRequestMessage::Function(
-                    async_openai::types::ChatCompletionRequestFunctionMessage {
-                        role: self.role,
-                        content: self.content,
-                        name: self
-                            .function_call
-                            .expect("function message function call should be valid")
-                            .name,
-                    },
-                )
+    fn coalesce_message_tokens(
+        &self,
+        messages: &[tiktoken_rs::ChatCompletionRequestMessage],
+        mut tokens: Vec<usize>,
+    ) -> Result<Vec<usize>, SplitError> {
+        if !self.coalesce_consecutive {
+            return Ok(tokens);
+        }
+        let runs: Vec<_> = coalescing_runs(messages)
+            .into_iter()
+            .filter(|run| run.len() > 1)
+            .collect();
+        if runs.is_empty() {
+            return Ok(tokens);
+        }
+
+        let coalesced: Vec<_> = runs
+            .iter()
+            .map(|run| coalesce(&messages[run.clone()]))
+            .collect();
+        let coalesced_tokens =
+            self.using_tokenizer(|tokenizer| tokenizer.count_message_tokens(&coalesced))?;
+        for (run, coalesced_tokens) in runs.into_iter().zip(coalesced_tokens) {
+            // The first message keeps its overhead,
+            // the others share the savings.
+            let others = run.len() - 1;
+            let mut saved = tokens[run.clone()]
+                .iter()
+                .sum::<usize>()
+                .saturating_sub(coalesced_tokens);
+            for (i, tokens) in tokens[run].iter_mut().skip(1).enumerate() {
+                let share = saved / (others - i);
+                *tokens = tokens.saturating_sub(share);
+                saved -= share;
             }
-            role @ async_openai::types::Role::Tool => panic!("unsupported role '{role}'"),
         }
+        Ok(tokens)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Call `f` with the configured tokenizer.
+    #[inline]
+    fn using_tokenizer<R>(&self, f: impl FnOnce(&dyn Tokenizer) -> R) -> R {
+        match (&self.tokenizer, self.bpe()) {
+            (Some(tokenizer), _) => f(tokenizer.0.as_ref()),
+            (None, Some(bpe)) => f(&CachedTiktokenTokenizer {
+                tokenizer: self.tiktoken_tokenizer(),
+                bpe,
+            }),
+            // Unsupported models fail when counting.
+            (None, None) => f(&self.tiktoken_tokenizer()),
+        }
+    }
 
-    #[test]
-    fn it_works() {
-        let messages: Vec<async_openai::types::ChatCompletionRequestMessage> = Vec::new();
+    /// Get the tokenizer of the model to use for tokenization,
+    /// building it only once,
+    /// or
+    /// `None` if it is not a supported chat model.
+    ///
+    /// Building it is expensive,
+    /// so it is shared by clones of the splitter,
+    /// including across threads,
+    /// until the model changes.
+    #[inline]
+    fn bpe(&self) -> Option<&CoreBPE> {
+        if self.tokenizer.is_some() {
+            return None;
+        }
+        self.bpe
+            .0
+            .get_or_init(|| {
+                let model = self.counting_model();
+                is_chat_model(model)
+                    .then(|| get_bpe_from_model(model).ok())
+                    .flatten()
+            })
+            .as_ref()
+    }
 
-        assert_eq!(ChatSplitter::default().split(&messages).0, &[]);
-        assert_eq!(ChatSplitter::default().split(&messages).1, &[]);
+    /// Get the default tokenizer for the model.
+    #[inline]
+    fn tiktoken_tokenizer(&self) -> TiktokenTokenizer {
+        let tokenizer = TiktokenTokenizer::new(self.counting_model());
+        match self.token_overhead {
+            Some((per_message, per_reply)) => tokenizer.with_overhead(per_message, per_reply),
+            None => tokenizer,
+        }
+    }
+
+    /// Get the context size the splitter assumes for the model,
+    /// i.e.,
+    /// the maximum number of input and generated tokens together.
+    ///
+    /// If [`tiktoken_rs`] gives an implausibly small context size,
+    /// e.g.,
+    /// zero,
+    /// `4096` is assumed instead,
+    /// and
+    /// a warning is logged once,
+    /// when the model is set.
+    /// If a custom [`Tokenizer`] is set,
+    /// its context size is used instead.
+    #[inline]
+    pub fn context_size(&self) -> usize {
+        match &self.tokenizer {
+            Some(tokenizer) if !self.estimated => tokenizer.0.context_size(),
+            _ => context_size(&self.model),
+        }
+    }
+
+    /// Set the per-message and per-reply token overhead of the default
+    /// tokenizer
+    /// (see [`TiktokenTokenizer::with_overhead`]).
+    ///
+    /// This is useful when the API changes before [`tiktoken_rs`] does.
+    /// Defaults to the overhead used by [`tiktoken_rs`],
+    /// which is four tokens per message for `gpt-3.5` models
+    /// and
+    /// three otherwise,
+    /// plus three tokens per reply.
+    /// It does not affect custom tokenizers
+    /// (see [`ChatSplitter::with_tokenizer`]).
+    #[inline]
+    #[must_use]
+    pub fn token_overhead(mut self, per_message: usize, per_reply: usize) -> Self {
+        self.token_overhead = Some((per_message, per_reply));
+        self
+    }
+
+    /// Set whether a leading system prompt should be pinned.
+    ///
+    /// When enabled and the first message has the `system` role,
+    /// or
+    /// the `developer` role that replaces it for newer models,
+    /// it is never considered 'outdated':
+    /// its tokens are reserved before computing the rest of the split.
+    /// Defaults to `false`.
+    #[inline]
+    #[must_use]
+    pub fn pin_system_prompt(mut self, pin_system_prompt: bool) -> Self {
+        self.pin_system_prompt = pin_system_prompt;
+        self
+    }
+
+    /// Set whether system messages identical to a prepended system prompt
+    /// should be dropped
+    /// (see [`ChatSplitter::try_split_with_system`]).
+    ///
+    /// When enabled,
+    /// stored system messages with exactly the same content as the prepended
+    /// one are 'outdated',
+    /// so that requests never have the same system prompt twice.
+    /// Defaults to `false`.
+    #[inline]
+    #[must_use]
+    pub fn dedupe_system(mut self, dedupe_system: bool) -> Self {
+        self.dedupe_system = dedupe_system;
+        self
+    }
+
+    /// Set whether the last message should be pinned.
+    ///
+    /// When enabled,
+    /// the last message is never considered 'outdated',
+    /// e.g.,
+    /// a volatile plan in an agent loop:
+    /// since messages are kept from the end,
+    /// its tokens are reserved first.
+    /// It is kept even if it does not fit the token limits,
+    /// together with a pinned system prompt
+    /// (see [`ChatSplitter::pin_system_prompt`]),
+    /// in which case a warning is logged,
+    /// but never if this exceeds [`ChatSplitter::max_messages`].
+    /// Defaults to `false`.
+    #[inline]
+    #[must_use]
+    pub fn pin_last(mut self, pin_last: bool) -> Self {
+        self.pin_last = pin_last;
+        self
+    }
+
+    /// Set where messages are dropped from.
+    ///
+    /// With [`TrimMode::Middle`],
+    /// the earliest [`ChatSplitter::head_messages`] messages are never
+    /// considered 'outdated',
+    /// just like a pinned system prompt
+    /// (see [`ChatSplitter::pin_system_prompt`]):
+    /// their tokens are reserved before computing the rest of the split,
+    /// and
+    /// [`ChatSplitter::try_split_three`] returns them separately.
+    /// Defaults to [`TrimMode::Front`].
+    #[inline]
+    #[must_use]
+    pub fn trim_mode(mut self, trim_mode: TrimMode) -> Self {
+        self.trim_mode = trim_mode;
+        self
+    }
+
+    /// Set the number of leading messages to keep when trimming the middle
+    /// (see [`ChatSplitter::trim_mode`]).
+    ///
+    /// Defaults to one.
+    #[inline]
+    #[must_use]
+    pub fn head_messages(mut self, head_messages: impl Into<usize>) -> Self {
+        self.head_messages = head_messages.into();
+        self
+    }
+
+    /// Set the number of leading messages to always keep,
+    /// e.g.,
+    /// few-shot examples.
+    ///
+    /// Just like a pinned system prompt
+    /// (see [`ChatSplitter::pin_system_prompt`]),
+    /// the first `keep_first` messages are never considered 'outdated':
+    /// their tokens are reserved before computing the rest of the split,
+    /// and
+    /// [`ChatSplitter::try_split_three`] returns them separately.
+    /// Unlike it,
+    /// this does not depend on their roles.
+    /// If they alone do not fit the token limits,
+    /// they are kept anyway,
+    /// and
+    /// a warning is logged.
+    /// Defaults to zero.
+    #[inline]
+    #[must_use]
+    pub fn keep_first(mut self, keep_first: impl Into<usize>) -> Self {
+        self.keep_first = keep_first.into();
+        self
+    }
+
+    /// Set whether consecutive messages of the same role should be counted as
+    /// one.
+    ///
+    /// When enabled,
+    /// adjacent messages with the same role and name,
+    /// and
+    /// without function calls,
+    /// are counted as a single message,
+    /// with their contents separated by newlines,
+    /// which saves the per-message overhead.
+    /// This only affects token counting:
+    /// messages are never merged,
+    /// so it is up to the caller to merge them before sending,
+    /// otherwise the counts are underestimated.
+    /// Within such a run of messages,
+    /// the savings are spread evenly,
+    /// so that only whole runs are counted exactly.
+    /// Defaults to `false`.
+    #[inline]
+    #[must_use]
+    pub fn coalesce_consecutive(mut self, coalesce_consecutive: bool) -> Self {
+        self.coalesce_consecutive = coalesce_consecutive;
+        self
+    }
+
+    /// Set the number of tokens between each two adjacent messages.
+    ///
+    /// This models chat templates that put delimiter tokens between messages,
+    /// e.g.,
+    /// for local models,
+    /// on top of the per-message overhead
+    /// (see [`ChatSplitter::token_overhead`]
+    /// and
+    /// [`ChatSplitter::with_tokenizer`]).
+    /// Separators are counted with each message but the first,
+    /// so a 'recent' suffix may be overestimated by one separator,
+    /// here and in [`IncrementalSplitter`] alike.
+    /// Coalesced messages have no separators between them
+    /// (see [`ChatSplitter::coalesce_consecutive`]).
+    /// Defaults to `0`,
+    /// as for OpenAI models.
+    #[inline]
+    #[must_use]
+    pub fn message_separator_tokens(mut self, message_separator_tokens: impl Into<usize>) -> Self {
+        self.message_separator_tokens = message_separator_tokens.into();
+        self
+    }
+
+    /// Set whether user/assistant pairs should be kept together.
+    ///
+    /// When enabled,
+    /// 'recent' messages never start with an `assistant` message whose
+    /// preceding `user` message is 'outdated':
+    /// the split position is moved forward past such messages.
+    /// If that would leave fewer than [`ChatSplitter::min_messages`] messages,
+    /// the minimum wins and the pair may be broken.
+    /// Defaults to `false`.
+    #[inline]
+    #[must_use]
+    pub fn keep_pairs(mut self, keep_pairs: bool) -> Self {
+        self.keep_pairs = keep_pairs;
+        self
+    }
+
+    /// Set whether tool calls and their responses should be kept together.
+    ///
+    /// When enabled,
+    /// 'recent' messages never start with a `tool` message whose
+    /// triggering `assistant` message is 'outdated',
+    /// since the API rejects tool responses that do not follow their call:
+    /// the split position is moved forward past such messages.
+    /// [`ChatSplitter::min_messages`] is applied afterwards,
+    /// so a chat too short to drop them may still start with a tool response.
+    /// Defaults to `false`.
+    #[inline]
+    #[must_use]
+    pub fn keep_tool_groups(mut self, keep_tool_groups: bool) -> Self {
+        self.keep_tool_groups = keep_tool_groups;
+        self
+    }
+
+    /// Set whether a last message too large to ever fit should be truncated.
+    ///
+    /// When enabled,
+    /// and the last message alone
+    /// (together with any pinned messages)
+    /// exceeds the token limits,
+    /// its content is truncated to fit,
+    /// so that it is 'recent' instead of 'outdated'.
+    /// This is lossy and mutates the message,
+    /// so it only applies to owned splits
+    /// (see [`ChatSplitter::try_split_owned`]),
+    /// and only to messages that give access to their content
+    /// (see [`IntoChatCompletionRequestMessage::content_mut`]).
+    /// Defaults to `false`.
+    #[inline]
+    #[must_use]
+    pub fn truncate_oversized(mut self, truncate_oversized: bool) -> Self {
+        self.truncate_oversized = truncate_oversized;
+        self
+    }
+
+    /// Set whether 'recent' messages should start at a turn boundary.
+    ///
+    /// A turn is a `user` message together with all messages following it
+    /// up to the next `user` message.
+    /// When enabled,
+    /// the split position is moved forward to the next `user` message,
+    /// so that 'recent' messages read naturally.
+    /// If there is no later `user` message,
+    /// the split position is left as it is instead of emptying 'recent'.
+    /// Snapping gives way to [`ChatSplitter::min_messages`],
+    /// so 'recent' may start mid-turn to keep that many messages.
+    /// Defaults to `false`.
+    #[inline]
+    #[must_use]
+    pub fn snap_to_turns(mut self, snap_to_turns: bool) -> Self {
+        self.snap_to_turns = snap_to_turns;
+        self
+    }
+
+    /// Set the estimated number of tokens each image in a message takes.
+    ///
+    /// Images are never tokenized,
+    /// so their cost has to be estimated,
+    /// e.g.,
+    /// `85` for low detail images.
+    /// Defaults to `765`,
+    /// which is the cost of a high detail 1024x1024 image.
+    #[inline]
+    #[must_use]
+    pub fn image_tokens(mut self, image_tokens: impl Into<usize>) -> Self {
+        self.image_tokens = image_tokens.into();
+        self
+    }
+
+    /// Set whether messages that cannot be tokenized exactly are an error.
+    ///
+    /// When enabled,
+    /// counting the tokens of a message with images fails
+    /// ([`SplitError::LossyConversion`]),
+    /// instead of estimating them
+    /// (see [`ChatSplitter::image_tokens`]),
+    /// so that token counts are never unreliable.
+    /// This applies to every method counting tokens,
+    /// e.g.,
+    /// [`ChatSplitter::try_split`].
+    /// Defaults to `false`.
+    #[inline]
+    #[must_use]
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Set the hysteresis of the token limits,
+    /// so that windows are stable across consecutive turns.
+    ///
+    /// Messages are only trimmed once they exceed the token limits
+    /// by more than `hysteresis_tokens`,
+    /// and
+    /// then they are trimmed down to `hysteresis_tokens` below the limits.
+    /// This way,
+    /// a message never flips in and out of 'recent' across turns.
+    /// Windows are only kept between calls by [`IncrementalSplitter`],
+    /// though:
+    /// otherwise,
+    /// this only delays trimming short chats.
+    /// The context size of the model is never exceeded.
+    /// Defaults to `0`.
+    #[inline]
+    #[must_use]
+    pub fn hysteresis_tokens(mut self, hysteresis_tokens: impl Into<usize>) -> Self {
+        self.hysteresis_tokens = hysteresis_tokens.into();
+        self
+    }
+
+    /// Set the limits to consider when splitting.
+    ///
+    /// With [`SplitStrategy::MessagesOnly`],
+    /// messages are never tokenized,
+    /// so any model can be used
+    /// and
+    /// splitting is cheap.
+    /// With [`SplitStrategy::TokensOnly`],
+    /// [`ChatSplitter::max_messages`] is ignored,
+    /// but [`ChatSplitter::message_limit`] is still enforced.
+    /// Defaults to [`SplitStrategy::Both`].
+    #[inline]
+    #[must_use]
+    pub fn strategy(mut self, strategy: SplitStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Set how much 'recent' messages may exceed the limits,
+    /// rather than dropping an important recent message.
+    ///
+    /// 'Recent' messages may have up to
+    /// [`SoftLimits::message_slack`] more messages than
+    /// [`ChatSplitter::max_messages`]
+    /// and
+    /// up to [`SoftLimits::token_slack`] more input tokens than the token
+    /// limits allow
+    /// (see [`ChatSplitter::max_tokens`]),
+    /// in which case the completion might get fewer tokens.
+    /// Hard limits are never exceeded, though:
+    /// neither [`ChatSplitter::message_limit`],
+    /// nor the context size of the model
+    /// (see [`ChatSplitter::context_size`]),
+    /// nor an input budget
+    /// (see [`ChatSplitter::try_split_by_budget`]).
+    /// Defaults to no slack.
+    #[inline]
+    #[must_use]
+    pub fn soft_limits(mut self, soft_limits: SoftLimits) -> Self {
+        self.soft_limits = soft_limits;
+        self
+    }
+
+    /// Check whether a message would be orphaned if it were the first
+    /// 'recent' message,
+    /// according to [`ChatSplitter::keep_pairs`]
+    /// and
+    /// [`ChatSplitter::keep_tool_groups`].
+    ///
+    /// # Errors
+    ///
+    /// If the message cannot be converted for tokenization.
+    #[inline]
+    fn is_orphan<M>(&self, message: &M) -> Result<bool, SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        if !self.keep_pairs && !self.keep_tool_groups {
+            return Ok(false);
+        }
+        let role = role(message)?;
+        Ok(self.keep_pairs && role == "assistant" || self.keep_tool_groups && role == "tool")
+    }
+
+    /// Get the position of the first message starting a turn,
+    /// i.e.,
+    /// the first `user` message,
+    /// if any.
+    ///
+    /// # Errors
+    ///
+    /// If some message cannot be converted for tokenization.
+    #[inline]
+    fn next_turn<M>(&self, messages: &[M]) -> Result<Option<usize>, SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        for (n, message) in messages.iter().enumerate() {
+            if role(message)? == "user" {
+                return Ok(Some(n));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Get the number of leading messages that are pinned,
+    /// either a system prompt,
+    /// the head kept when trimming the middle,
+    /// or
+    /// the first messages always kept.
+    ///
+    /// # Errors
+    ///
+    /// If some message cannot be converted for tokenization.
+    #[inline]
+    fn pinned<M>(&self, messages: &[M]) -> Result<usize, SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        let head = match self.trim_mode {
+            TrimMode::Front => 0,
+            TrimMode::Middle => self.head_messages.min(messages.len()),
+        }
+        .max(self.keep_first.min(messages.len()));
+        if !self.pin_system_prompt {
+            return Ok(head);
+        }
+        match messages.first() {
+            Some(message) if is_system_role(&role(message)?) => Ok(head.max(1)),
+            _ => Ok(head),
+        }
+    }
+
+    /// Get the maximum number of messages that can still be added,
+    /// given that `reserved` messages are already taken.
+    #[inline]
+    fn max_messages_after(&self, reserved: usize) -> usize {
+        let max_messages = if self.strategy.uses_messages() {
+            self.max_messages
+                .saturating_add(self.soft_limits.message_slack)
+                .min(self.message_limit)
+        } else {
+            self.message_limit
+        };
+        max_messages.saturating_sub(self.prefix.0 + reserved)
+    }
+
+    /// Get a split position by only considering `max_messages`,
+    /// given that `reserved` messages are already taken,
+    /// e.g.,
+    /// pinned ones not passed in `messages`.
+    ///
+    /// This is a building block of [`ChatSplitter::try_split`]
+    /// for custom split strategies:
+    /// the returned index `n` is the smallest one such that
+    /// `messages[n..]` satisfies the message limits
+    /// (see [`ChatSplitter::max_messages`] and [`ChatSplitter::soft_limits`]),
+    /// and
+    /// everything else,
+    /// including tokens,
+    /// pinned messages and
+    /// [`ChatSplitter::min_messages`],
+    /// is ignored.
+    /// Use [`ChatSplitter::try_split_position`] for the supported split.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use chat_splitter::ChatSplitter;
+    /// use chat_splitter::SimpleMessage;
+    ///
+    /// let messages = [
+    ///     SimpleMessage::new("system", "You are a helpful assistant."),
+    ///     SimpleMessage::new("user", "Who won the world series in 2020?"),
+    ///     SimpleMessage::new("assistant", "The Los Angeles Dodgers."),
+    /// ];
+    /// let splitter = ChatSplitter::default().max_messages(2_usize);
+    ///
+    /// // Keep the system prompt apart,
+    /// // so that it counts towards the limit without being dropped.
+    /// let (system, rest) = messages.split_at(1);
+    /// let n = splitter.position_by_max_messages(system.len(), rest);
+    /// assert_eq!(&rest[n..], &messages[2..]);
+    /// ```
+    #[inline]
+    pub fn position_by_max_messages<M>(&self, reserved: usize, messages: &[M]) -> usize {
+        let upper_limit = self.max_messages_after(reserved);
+
+        // Saturates at zero when everything fits,
+        // however large the limits are.
+        let n = messages.len().saturating_sub(upper_limit);
+        debug_assert!(messages[n..].len() <= upper_limit);
+        n
+    }
+
+    /// Get the maximum number of input tokens,
+    /// according to `max_tokens`,
+    /// `max_input_tokens`,
+    /// the reserved tokens
+    /// and
+    /// the safety margin,
+    /// unless overridden by an input budget.
+    ///
+    /// # Errors
+    ///
+    /// If `max_tokens` leaves no room for input.
+    #[inline]
+    fn input_token_limit(&self) -> Result<usize, SplitError> {
+        if let Some(input_budget) = self.input_budget {
+            return Ok(input_budget);
+        }
+        let max_tokens = self.max_tokens as usize;
+        let context_size = self.combined_size();
+        if max_tokens >= context_size {
+            return Err(SplitError::CompletionBudgetTooLarge {
+                max_tokens,
+                context_size,
+            });
+        }
+        let upper_limit = context_size - max_tokens;
+        Ok(self
+            .max_input_tokens
+            .map_or(upper_limit, |max_input_tokens| {
+                upper_limit.min(max_input_tokens as usize)
+            })
+            .saturating_sub(self.reserved_tokens)
+            .saturating_sub(self.prefix.1)
+            .saturating_sub(self.safety_margin_tokens as usize)
+            .saturating_add(self.soft_limits.token_slack)
+            .min(self.context_size()))
+    }
+
+    /// Check whether the messages certainly fit the token limits,
+    /// without tokenizing them.
+    ///
+    /// This only applies to the default tokenizer,
+    /// where every token takes at least one byte,
+    /// so that the number of bytes is an upper bound on the number of tokens.
+    ///
+    /// # Errors
+    ///
+    /// If some message cannot be converted for tokenization,
+    /// or if `max_tokens` leaves no room for input.
+    #[inline]
+    fn fits_without_tokenizing<M>(
+        &self,
+        converted: &[tiktoken_rs::ChatCompletionRequestMessage],
+        messages: &[M],
+    ) -> Result<bool, SplitError>
+    where
+        M: IntoChatCompletionRequestMessage,
+    {
+        if self.tokenizer.is_some() || !self.strategy.uses_tokens() {
+            return Ok(false);
+        }
+        // Unsupported models have to fail the same way as when tokenizing.
+        if !is_chat_model(self.counting_model()) {
+            return Ok(false);
+        }
+
+        // A name adds at most one more token to the per-message overhead.
+        let (tokens_per_message, tokens_per_reply) = self.tiktoken_tokenizer().overhead();
+        let max_tokens = converted
+            .iter()
+            .map(|message| {
+                tokens_per_message
+                    + message.role.len()
+                    + message.content.as_ref().map_or(0, String::len)
+                    + message.name.as_ref().map_or(0, |name| name.len() + 1)
+                    + message.function_call.as_ref().map_or(0, |function_call| {
+                        function_call.name.len() + function_call.arguments.len()
+                    })
+            })
+            .sum::<usize>()
+            + tokens_per_reply
+            + self.separator_tokens(converted.len())
+            + self.count_image_tokens(messages)?;
+        Ok(max_tokens <= self.input_token_limit()?)
+    }
+
+    /// Get a split position by only considering `max_tokens`,
+    /// given the tokens each message takes
+    /// and
+    /// that `reserved_tokens` are always sent,
+    /// e.g.,
+    /// for the reply and pinned messages.
+    ///
+    /// This is a building block of [`ChatSplitter::try_split`]
+    /// for custom split strategies:
+    /// the returned index `n` is the smallest one such that
+    /// `message_tokens[n..]` fits the input token limit,
+    /// found by binary search
+    /// (see [`ChatSplitter::max_tokens`],
+    /// [`ChatSplitter::max_input_tokens`] and
+    /// [`ChatSplitter::hysteresis_tokens`]),
+    /// or
+    /// `message_tokens.len()` if not even the last message fits.
+    /// Everything else,
+    /// including the message limits,
+    /// pinned messages and
+    /// [`ChatSplitter::min_messages`],
+    /// is ignored.
+    /// Token counts can be taken from [`ChatSplitter::message_tokens`],
+    /// and
+    /// the per-reply overhead from [`ChatSplitter::count_tokens`] with no
+    /// messages.
+    /// Use [`ChatSplitter::try_split_position`] for the supported split.
+    ///
+    /// # Errors
+    ///
+    /// If [`ChatSplitter::max_tokens`] is not smaller than the context size
+    /// ([`SplitError::CompletionBudgetTooLarge`]).
+    ///
+    /// # Examples
+    ///
+    /// Applying the message limit after the token limit,
+    /// instead of before:
+    ///
+    /// ```
+    /// use chat_splitter::ChatSplitter;
+    /// use chat_splitter::SimpleMessage;
+    ///
+    /// let messages = [
+    ///     SimpleMessage::new("user", "Who won the world series in 2020?"),
+    ///     SimpleMessage::new("assistant", "The Los Angeles Dodgers."),
+    ///     SimpleMessage::new("user", "Where was it played?"),
+    /// ];
+    /// let splitter = ChatSplitter::default().max_messages(2_usize);
+    ///
+    /// let tokens = splitter.message_tokens(&messages)?;
+    /// let reply_tokens = splitter.count_tokens::<SimpleMessage>(&[])?;
+    /// let mut n = splitter.position_by_max_tokens(reply_tokens, &tokens)?;
+    /// n += splitter.position_by_max_messages(0, &messages[n..]);
+    /// assert_eq!(n, 1);
+    /// assert_eq!(n, splitter.split_position(&messages));
+    /// # Ok::<(), chat_splitter::SplitError>(())
+    /// ```
+    #[inline]
+    pub fn position_by_max_tokens(
+        &self,
+        reserved_tokens: usize,
+        message_tokens: &[usize],
+    ) -> Result<usize, SplitError> {
+        let limit = self.input_token_limit()?;
+        // Nothing is trimmed up to the limit plus the hysteresis,
+        // but then everything is trimmed down to the limit minus it.
+        let untrimmed_limit = limit
+            .saturating_add(self.hysteresis_tokens)
+            .min(self.context_size())
+            .max(limit);
+        let upper_limit = limit.saturating_sub(self.hysteresis_tokens);
+
+        // Tokens of each suffix `messages[n..]`,
+        // so that the search below never needs to tokenize again.
+        let mut suffix_tokens = vec![0; message_tokens.len() + 1];
+        for (n, tokens) in message_tokens.iter().enumerate().rev() {
+            suffix_tokens[n] = suffix_tokens[n + 1] + tokens;
+        }
+        let tokens_from = |n: usize| reserved_tokens + suffix_tokens[n];
+        let fits_from = |n: usize, upper_limit: usize| {
+            let tokens = tokens_from(n);
+            if let Some(ProbeHook(on_probe)) = &self.on_probe {
+                on_probe(n, tokens);
+            }
+            tokens <= upper_limit
+        };
+
+        if fits_from(0, untrimmed_limit) {
+            // The search below never probes the whole chat,
+            // so it has to be checked separately.
+            return Ok(0);
+        }
+
+        // A suffix whose tokens land exactly on `upper_limit` fits,
+        // so the comparison below never yields `Ordering::Equal`
+        // and the search deterministically narrows down to the smallest `n`
+        // whose suffix fits.
+        let (n, _range) = (0..=message_tokens.len()).binary_any(|n| {
+            debug_assert!(n < message_tokens.len());
+
+            if fits_from(n, upper_limit) {
+                Ordering::Greater
+            } else {
+                Ordering::Less
+            }
+        });
+
+        // The hook only observes the search itself,
+        // so that debug and release builds report the same probes.
+        debug_assert!(n == message_tokens.len() || tokens_from(n) <= upper_limit);
+        Ok(n)
+    }
+
+    /// Get the range of 'outdated' messages by first considering the
+    /// `max_messages` limit,
+    /// then
+    /// the `max_tokens` limit.
+    ///
+    /// Everything outside the returned range is 'recent'.
+    ///
+    /// # Errors
+    ///
+    /// If tokenizer for the specified model is not found or is not a supported
+    /// chat model,
+    /// if some message cannot be converted for tokenization,
+    /// or if [`ChatSplitter::max_tokens`] is not smaller than the context size.
+    #[inline]
+    fn outdated<M>(&self, messages: &[M]) -> Result<Range<usize>, SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        Ok(self.plan_of(messages)?.outdated())
+    }
+
+    /// Get the plan of a split.
+    ///
+    /// # Errors
+    ///
+    /// If tokenizer for the specified model is not found or is not a supported
+    /// chat model,
+    /// if some message cannot be converted for tokenization,
+    /// or if [`ChatSplitter::max_tokens`] is not smaller than the context size.
+    #[inline]
+    fn plan_of<M>(&self, messages: &[M]) -> Result<SplitPlan, SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        if !self.strategy.uses_tokens() {
+            return self.plan_with_tokens(messages, None);
+        }
+        // Messages are converted only once,
+        // since this clones them.
+        let converted = try_into_tiktoken_rs(messages)?;
+        if self.fits_without_tokenizing(&converted, messages)? {
+            return self.plan_with_tokens(messages, None);
+        }
+        let tokens = self.count_message_tokens(&converted, messages)?;
+        drop(converted);
+        self.plan_with_tokens(messages, Some(&tokens))
+    }
+
+    /// Count the tokens each of the given messages take,
+    /// given their converted versions,
+    /// including their images.
+    ///
+    /// # Errors
+    ///
+    /// If tokenizer for the specified model is not found or is not a supported
+    /// chat model.
+    #[inline]
+    fn count_message_tokens<M>(
+        &self,
+        converted: &[tiktoken_rs::ChatCompletionRequestMessage],
+        messages: &[M],
+    ) -> Result<Vec<usize>, SplitError>
+    where
+        M: IntoChatCompletionRequestMessage,
+    {
+        let mut tokens = self.count_tiktoken_rs_message_tokens(converted)?;
+        for (index, (tokens, message)) in tokens.iter_mut().zip(messages).enumerate() {
+            *tokens += self.message_image_tokens(index, message)?;
+        }
+        Ok(tokens)
+    }
+
+    /// Get the tokens each message takes,
+    /// or all zeros if tokens are not considered
+    /// (see [`ChatSplitter::strategy`]).
+    ///
+    /// These are the counts [`ChatSplitter::try_split`] works with,
+    /// e.g.,
+    /// for [`ChatSplitter::position_by_max_tokens`]:
+    /// together with the per-reply overhead,
+    /// they add up to [`ChatSplitter::count_tokens`].
+    ///
+    /// # Errors
+    ///
+    /// If tokenizer for the specified model is not found or is not a supported
+    /// chat model
+    /// ([`SplitError::UnknownModel`]),
+    /// or if some message cannot be converted for tokenization
+    /// ([`SplitError::UnsupportedRole`]).
+    #[inline]
+    pub fn message_tokens<M>(&self, messages: &[M]) -> Result<Vec<usize>, SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        if !self.strategy.uses_tokens() {
+            return Ok(vec![0; messages.len()]);
+        }
+        self.count_message_tokens(&try_into_tiktoken_rs(messages)?, messages)
+    }
+
+    /// Get the range of 'outdated' messages,
+    /// given the tokens each message takes,
+    /// or
+    /// `None` if the messages are known to fit the token limits.
+    ///
+    /// # Errors
+    ///
+    /// If tokenizer for the specified model is not found or is not a supported
+    /// chat model,
+    /// if some message cannot be converted for tokenization,
+    /// or if [`ChatSplitter::max_tokens`] is not smaller than the context size.
+    #[inline]
+    fn outdated_with_tokens<M>(
+        &self,
+        messages: &[M],
+        tokens: Option<&[usize]>,
+    ) -> Result<Range<usize>, SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        Ok(self.plan_with_tokens(messages, tokens)?.outdated())
+    }
+
+    /// Get the plan of a split,
+    /// given the tokens each message takes,
+    /// or
+    /// `None` if the messages are known to fit the token limits.
+    ///
+    /// # Errors
+    ///
+    /// If tokenizer for the specified model is not found or is not a supported
+    /// chat model,
+    /// if some message cannot be converted for tokenization,
+    /// or if [`ChatSplitter::max_tokens`] is not smaller than the context size.
+    #[inline]
+    fn plan_with_tokens<M>(
+        &self,
+        messages: &[M],
+        tokens: Option<&[usize]>,
+    ) -> Result<SplitPlan, SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        let (pinned, messages) = messages.split_at(self.pinned(messages)?);
+
+        let max_messages_n = self.position_by_max_messages(pinned.len(), messages);
+        let mut n = max_messages_n;
+        let mut max_tokens_n = 0;
+        if let Some(tokens) = tokens.filter(|_| self.strategy.uses_tokens()) {
+            debug_assert_eq!(pinned.len() + messages.len(), tokens.len());
+            let (pinned_tokens, tokens) = tokens.split_at(pinned.len());
+            let reserved_tokens =
+                self.count_tiktoken_rs_tokens(&[])? + pinned_tokens.iter().sum::<usize>();
+            let upper_limit = self.input_token_limit()?;
+            if reserved_tokens > upper_limit {
+                log::warn!("pinned tokens = {reserved_tokens} > {upper_limit}");
+            }
+            max_tokens_n = self.position_by_max_tokens(reserved_tokens, &tokens[n..])?;
+            n += max_tokens_n;
+        }
+        let limited_n = n;
+        let mut snapped_n = n;
+        if n > 0 {
+            while n < messages.len() && self.is_orphan(&messages[n])? {
+                n += 1;
+            }
+            snapped_n = n;
+            if self.snap_to_turns {
+                // Without a later turn,
+                // 'recent' is better left as it is than emptied.
+                if let Some(turn) = self.next_turn(&messages[n..])? {
+                    n += turn;
+                }
+            }
+        }
+        let adjusted_n = n;
+
+        let min_messages = self
+            .min_messages
+            .saturating_sub(pinned.len())
+            .min(self.max_messages_after(pinned.len()));
+        let max_n = messages.len().saturating_sub(min_messages);
+        if n > max_n {
+            log::warn!(
+                "min_messages = {} overrides max_tokens = {}",
+                self.min_messages,
+                self.max_tokens
+            );
+            n = max_n;
+        }
+        if self.pin_last && n > 0 && n == messages.len() {
+            log::warn!(
+                "pinned last message overrides max_tokens = {}",
+                self.max_tokens
+            );
+            n -= 1;
+        }
+
+        // Whatever moved the boundary above,
+        // `max_messages` is never exceeded.
+        n = n.max(max_messages_n);
+        if pinned.is_empty() && !messages.is_empty() && n == messages.len() {
+            if max_messages_n == messages.len() {
+                log::warn!(
+                    "no recent messages, max_messages = {}",
+                    self.max_messages_after(0)
+                );
+            } else if limited_n == messages.len() {
+                log::warn!(
+                    "no recent messages, last message does not fit max_tokens = {}",
+                    self.max_tokens
+                );
+            } else {
+                log::warn!("no recent messages, all of them were kept with outdated ones");
+            }
+        }
+        Ok(SplitPlan {
+            position: pinned.len() + n,
+            pinned_messages: pinned.len(),
+            outdated_messages: n,
+            recent_messages: messages.len() - n,
+            max_messages_hit: max_messages_n > 0,
+            max_tokens_hit: max_tokens_n > 0,
+            kept_pairs: snapped_n > limited_n,
+            snapped_to_turn: adjusted_n > snapped_n,
+            min_messages_hit: n < adjusted_n,
+        })
+    }
+
+    /// Get the position at which the chat would be split.
+    ///
+    /// This is the same as [`ChatSplitter::try_split_position`],
+    /// but panics instead of returning an error.
+    ///
+    /// # Panics
+    ///
+    /// If [`ChatSplitter::try_split_position`] fails.
+    #[inline]
+    pub fn split_position<M>(&self, messages: &[M]) -> usize
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        self.try_split_position(messages).unwrap()
+    }
+
+    /// Get the position at which the chat would be split.
+    ///
+    /// The returned index `n` is such that
+    /// `messages[..n]` are the 'outdated' messages
+    /// and
+    /// `messages[n..]` are the 'recent' ones,
+    /// as returned by [`ChatSplitter::try_split`],
+    /// except for the pinned messages at the start,
+    /// which are never 'outdated':
+    /// a pinned system prompt
+    /// (see [`ChatSplitter::pin_system_prompt`]),
+    /// the head kept when trimming the middle
+    /// (see [`ChatSplitter::trim_mode`])
+    /// or the first messages always kept
+    /// (see [`ChatSplitter::keep_first`]).
+    /// Use [`ChatSplitter::try_split_ranges`] to tell them apart.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`ChatSplitter::try_split`].
+    #[inline]
+    pub fn try_split_position<M>(&self, messages: &[M]) -> Result<usize, SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        Ok(self.outdated(messages)?.end)
+    }
+
+    /// Get the index ranges of the pinned,
+    /// the 'outdated'
+    /// and
+    /// the 'recent' messages.
+    ///
+    /// This is the same as [`ChatSplitter::try_split_ranges`],
+    /// but panics instead of returning an error.
+    ///
+    /// # Panics
+    ///
+    /// If [`ChatSplitter::try_split_ranges`] fails.
+    #[inline]
+    pub fn split_ranges<M>(&self, messages: &[M]) -> (Range<usize>, Range<usize>, Range<usize>)
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        self.try_split_ranges(messages).unwrap()
+    }
+
+    /// Get the index ranges of the pinned,
+    /// the 'outdated'
+    /// and
+    /// the 'recent' messages.
+    ///
+    /// This returns `0..p`,
+    /// `p..n`
+    /// and
+    /// `n..messages.len()`,
+    /// where `p` is the number of pinned messages,
+    /// as in the 'head' of [`ChatSplitter::try_split_three`],
+    /// and
+    /// `n` is given by [`ChatSplitter::try_split_position`],
+    /// so the three ranges always partition the whole chat.
+    /// The 'recent' messages of [`ChatSplitter::try_split`] are the ones in
+    /// the first range followed by the ones in the last.
+    /// This is useful to keep track of messages by their indices,
+    /// e.g.,
+    /// when persisting them.
+    ///
+    /// # Errors
+    ///
+    /// If tokenizer for the specified model is not found or is not a supported
+    /// chat model
+    /// ([`SplitError::UnknownModel`]),
+    /// if some message cannot be converted for tokenization
+    /// ([`SplitError::UnsupportedRole`]),
+    /// or if [`ChatSplitter::max_tokens`] is not smaller than the context size
+    /// ([`SplitError::CompletionBudgetTooLarge`]).
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    pub fn try_split_ranges<M>(
+        &self,
+        messages: &[M],
+    ) -> Result<(Range<usize>, Range<usize>, Range<usize>), SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        let outdated = self.outdated(messages)?;
+        Ok((
+            0..outdated.start,
+            outdated.clone(),
+            outdated.end..messages.len(),
+        ))
+    }
+
+    /// Get the plan of a split.
+    ///
+    /// This is the same as [`ChatSplitter::try_plan`],
+    /// but panics instead of returning an error.
+    ///
+    /// # Panics
+    ///
+    /// If [`ChatSplitter::try_plan`] fails.
+    #[inline]
+    pub fn plan<M>(&self, messages: &[M]) -> SplitPlan
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        self.try_plan(messages).unwrap()
+    }
+
+    /// Get the plan of a split,
+    /// i.e.,
+    /// where the chat would be split
+    /// and
+    /// which limits and options moved the boundary.
+    ///
+    /// Nothing is borrowed,
+    /// so the messages can be inspected or changed before applying the plan,
+    /// e.g.,
+    /// with `messages.split_at(plan.position)`.
+    ///
+    /// # Errors
+    ///
+    /// If tokenizer for the specified model is not found or is not a supported
+    /// chat model
+    /// ([`SplitError::UnknownModel`]),
+    /// if some message cannot be converted for tokenization
+    /// ([`SplitError::UnsupportedRole`]),
+    /// or if [`ChatSplitter::max_tokens`] is not smaller than the context size
+    /// ([`SplitError::CompletionBudgetTooLarge`]).
+    #[inline]
+    pub fn try_plan<M>(&self, messages: &[M]) -> Result<SplitPlan, SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        self.plan_of(messages)
+    }
+
+    /// Check whether the whole chat already fits.
+    ///
+    /// This is the same as [`ChatSplitter::try_fits`],
+    /// but panics instead of returning an error.
+    ///
+    /// # Panics
+    ///
+    /// If [`ChatSplitter::try_fits`] fails.
+    #[inline]
+    pub fn fits<M>(&self, messages: &[M]) -> bool
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        self.try_fits(messages).unwrap()
+    }
+
+    /// Check whether the whole chat already fits.
+    ///
+    /// This is `true` exactly when [`ChatSplitter::try_split`] would
+    /// return no 'outdated' messages,
+    /// i.e.,
+    /// when all messages satisfy the given limits.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`ChatSplitter::try_split`].
+    #[inline]
+    pub fn try_fits<M>(&self, messages: &[M]) -> Result<bool, SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        Ok(self.outdated(messages)?.is_empty())
+    }
+
+    /// Get how many trailing messages fit the token limits.
+    ///
+    /// This is the same as [`ChatSplitter::try_fitting_message_count`],
+    /// but panics instead of returning an error.
+    ///
+    /// # Panics
+    ///
+    /// If [`ChatSplitter::try_fitting_message_count`] fails.
+    #[inline]
+    pub fn fitting_message_count<M>(&self, messages: &[M]) -> usize
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        self.try_fitting_message_count(messages).unwrap()
+    }
+
+    /// Get how many trailing messages fit the token limits,
+    /// e.g.,
+    /// to tell users how many messages they can keep.
+    ///
+    /// Only the token limits are considered:
+    /// message limits and all other options are ignored.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`ChatSplitter::try_split`].
+    #[inline]
+    pub fn try_fitting_message_count<M>(&self, messages: &[M]) -> Result<usize, SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        let tokens = self.count_message_tokens(&try_into_tiktoken_rs(messages)?, messages)?;
+        let reply_tokens = self.count_tiktoken_rs_tokens(&[])?;
+        Ok(messages.len() - self.position_by_max_tokens(reply_tokens, &tokens)?)
+    }
+
+    /// Get the first of the given models whose context can hold the whole
+    /// chat,
+    /// while still leaving [`ChatSplitter::max_tokens`] for the completion.
+    ///
+    /// Models are tried in the given order,
+    /// e.g.,
+    /// from the cheapest to the most expensive,
+    /// each as if set with [`ChatSplitter::model`],
+    /// so with its own context size.
+    /// Everything else is kept,
+    /// so the chat is counted
+    /// (see [`ChatSplitter::count_tokens`])
+    /// and
+    /// limited
+    /// (e.g.,
+    /// by [`ChatSplitter::reserve_prefix`]
+    /// or [`ChatSplitter::combined_ceiling`])
+    /// exactly as when splitting,
+    /// and
+    /// a custom tokenizer
+    /// (see [`ChatSplitter::with_tokenizer`])
+    /// still takes precedence over the models.
+    /// Models that are not supported are skipped.
+    /// `None` means that none of the models can hold the chat.
+    #[inline]
+    pub fn best_fit<M>(&self, messages: &[M], models: &[&str]) -> Option<String>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        models
+            .iter()
+            .find(|&&model| {
+                let splitter = self.clone().model(model);
+                splitter
+                    .count_tokens(messages)
+                    .and_then(|tokens| Ok(tokens <= splitter.input_token_limit()?))
+                    .unwrap_or(false)
+            })
+            .map(|&model| model.to_owned())
+    }
+
+    /// Get a summary of how the chat would be split.
+    ///
+    /// This is the same as [`ChatSplitter::try_split_summary`],
+    /// but panics instead of returning an error.
+    ///
+    /// # Panics
+    ///
+    /// If [`ChatSplitter::try_split_summary`] fails.
+    #[inline]
+    pub fn split_summary<M>(&self, messages: &[M]) -> SplitSummary
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        self.try_split_summary(messages).unwrap()
+    }
+
+    /// Get a summary of how the chat would be split.
+    ///
+    /// Besides the split position,
+    /// the summary contains how many input tokens each group of messages
+    /// takes,
+    /// exactly as counted for the model.
+    /// This is useful for logging and for monitoring context utilization.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`ChatSplitter::try_split`].
+    #[inline]
+    pub fn try_split_summary<M>(&self, messages: &[M]) -> Result<SplitSummary, SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        Ok(self.summary(messages)?.1)
+    }
+
+    /// Get the plan of a split together with its summary,
+    /// tokenizing every message only once.
+    ///
+    /// # Errors
+    ///
+    /// If tokenizer for the specified model is not found or is not a supported
+    /// chat model,
+    /// if some message cannot be converted for tokenization,
+    /// or if [`ChatSplitter::max_tokens`] is not smaller than the context size.
+    #[inline]
+    fn summary<M>(&self, messages: &[M]) -> Result<(SplitPlan, SplitSummary), SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        let tokens = self.count_message_tokens(&try_into_tiktoken_rs(messages)?, messages)?;
+        let plan = self.plan_with_tokens(messages, Some(&tokens))?;
+        let reply_tokens = self.count_tiktoken_rs_tokens(&[])?;
+
+        let outdated_tokens: usize = tokens[plan.outdated()].iter().sum();
+        let summary = SplitSummary {
+            position: plan.position,
+            outdated_tokens: reply_tokens + outdated_tokens,
+            recent_tokens: reply_tokens + tokens.iter().sum::<usize>() - outdated_tokens,
+        };
+        Ok((plan, summary))
+    }
+
+    /// Split the chat into two groups of messages,
+    /// the 'outdated' and the
+    /// 'recent' ones.
+    ///
+    /// This is the same as [`ChatSplitter::try_split`],
+    /// but panics instead of returning an error.
+    ///
+    /// For a detailed usage example,
+    /// see [`examples/chat.rs`](https://github.com/schneiderfelipe/chat-splitter/blob/main/examples/chat.rs).
+    ///
+    /// # Panics
+    ///
+    /// If tokenizer for the specified model is not found or is not a supported
+    /// chat model,
+    /// if some message cannot be converted for tokenization,
+    /// or if [`ChatSplitter::max_tokens`] is not smaller than the context size.
+    #[inline]
+    pub fn split<'a, M>(&self, messages: &'a [M]) -> (&'a [M], &'a [M])
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        self.try_split(messages).unwrap()
+    }
+
+    /// Split the chat into two groups of messages,
+    /// the 'outdated' and the
+    /// 'recent' ones.
+    ///
+    /// The 'recent' messages are guaranteed to satisfy the given limits,
+    /// while
+    /// the 'outdated' ones contain all the ones before 'recent'.
+    ///
+    /// If a leading system prompt is pinned
+    /// (see [`ChatSplitter::pin_system_prompt`]),
+    /// it is left out of 'outdated',
+    /// but since slices are contiguous,
+    /// it is not part of the returned 'recent' slice either:
+    /// it is always `messages[0]` and should be sent before 'recent'.
+    /// The same goes for the head kept when trimming the middle
+    /// (see [`ChatSplitter::trim_mode`])
+    /// and the first messages always kept
+    /// (see [`ChatSplitter::keep_first`]).
+    /// Use [`ChatSplitter::try_split_three`] to get them separately
+    /// or
+    /// [`ChatSplitter::try_split_owned`] to get them as part of 'recent'.
+    ///
+    /// If nothing is left to send,
+    /// e.g.,
+    /// because the last message alone exceeds the token limits,
+    /// 'recent' is empty
+    /// and
+    /// a warning is logged,
+    /// since the API rejects empty chats.
+    ///
+    /// # Errors
+    ///
+    /// If tokenizer for the specified model is not found or is not a supported
+    /// chat model
+    /// ([`SplitError::UnknownModel`]),
+    /// if some message cannot be converted for tokenization
+    /// ([`SplitError::UnsupportedRole`]),
+    /// or if [`ChatSplitter::max_tokens`] is not smaller than the context size
+    /// ([`SplitError::CompletionBudgetTooLarge`]).
+    #[inline]
+    pub fn try_split<'a, M>(&self, messages: &'a [M]) -> Result<(&'a [M], &'a [M]), SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        Ok(self.plan_of(messages)?.split(messages))
+    }
+
+    /// Split a chat given as references into two groups of messages,
+    /// the 'outdated' and the
+    /// 'recent' ones.
+    ///
+    /// This is the same as [`ChatSplitter::try_split_refs`],
+    /// but panics instead of returning an error.
+    ///
+    /// # Panics
+    ///
+    /// If [`ChatSplitter::try_split_refs`] fails.
+    #[inline]
+    pub fn split_refs<'a, 'b, M>(&self, messages: &'a [&'b M]) -> (&'a [&'b M], &'a [&'b M])
+    where
+        M: ToChatCompletionRequestMessage + ?Sized,
+    {
+        self.try_split_refs(messages).unwrap()
+    }
+
+    /// Split a chat given as references into two groups of messages,
+    /// the 'outdated' and the
+    /// 'recent' ones,
+    /// e.g.,
+    /// when picking messages from a larger arena.
+    ///
+    /// This is the same as [`ChatSplitter::try_split`] on the references,
+    /// but only requires the messages to be
+    /// [`ToChatCompletionRequestMessage`],
+    /// which is implemented for every cloneable message type
+    /// and can be implemented directly for types that are not [`Clone`].
+    /// Messages are only ever converted by reference,
+    /// so the references are copied,
+    /// but the messages themselves are not cloned
+    /// unless their [`ToChatCompletionRequestMessage`] implementation does so.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`ChatSplitter::try_split`].
+    #[inline]
+    pub fn try_split_refs<'a, 'b, M>(
+        &self,
+        messages: &'a [&'b M],
+    ) -> Result<(&'a [&'b M], &'a [&'b M]), SplitError>
+    where
+        M: ToChatCompletionRequestMessage + ?Sized,
+    {
+        self.try_split(messages)
+    }
+
+    /// Split the chat into two groups of messages,
+    /// always keeping the ones from the given index on.
+    ///
+    /// This is the same as [`ChatSplitter::try_split_with_floor_index`],
+    /// but panics instead of returning an error.
+    ///
+    /// # Panics
+    ///
+    /// If [`ChatSplitter::try_split_with_floor_index`] fails.
+    #[inline]
+    pub fn split_with_floor_index<'a, M>(
+        &self,
+        messages: &'a [M],
+        floor: usize,
+    ) -> (&'a [M], &'a [M])
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        self.try_split_with_floor_index(messages, floor).unwrap()
+    }
+
+    /// Split the chat into two groups of messages,
+    /// the 'outdated' and the
+    /// 'recent' ones,
+    /// where `messages[floor..]` are always 'recent'.
+    ///
+    /// This is useful to always keep,
+    /// e.g.,
+    /// the messages of the last hour,
+    /// plus as many older ones as fit.
+    /// The limits only trim messages before `floor`,
+    /// just like in [`ChatSplitter::try_split`],
+    /// including pinned messages being in neither of the returned slices.
+    /// If the messages from `floor` on exceed the limits by themselves,
+    /// they are still all 'recent',
+    /// so that the limits are exceeded,
+    /// and
+    /// a warning is logged.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`ChatSplitter::try_split`].
+    #[inline]
+    pub fn try_split_with_floor_index<'a, M>(
+        &self,
+        messages: &'a [M],
+        floor: usize,
+    ) -> Result<(&'a [M], &'a [M]), SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        let outdated = self.outdated(messages)?;
+        let floor = floor.clamp(outdated.start, messages.len());
+        if outdated.end > floor {
+            log::warn!(
+                "floor = {floor} overrides max_tokens = {} and max_messages = {}",
+                self.max_tokens,
+                self.max_messages
+            );
+        }
+        let position = outdated.end.min(floor);
+        Ok((&messages[outdated.start..position], &messages[position..]))
+    }
+
+    /// Split a chat stored newest first into two groups of messages,
+    /// the 'recent' and the
+    /// 'outdated' ones.
+    ///
+    /// This is the same as [`ChatSplitter::try_split_reversed`],
+    /// but panics instead of returning an error.
+    ///
+    /// # Panics
+    ///
+    /// If [`ChatSplitter::try_split_reversed`] fails.
+    #[inline]
+    pub fn split_reversed<'a, M>(&self, messages: &'a [M]) -> (&'a [M], &'a [M])
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        self.try_split_reversed(messages).unwrap()
+    }
+
+    /// Split a chat stored newest first into two groups of messages,
+    /// the 'recent' and the
+    /// 'outdated' ones.
+    ///
+    /// Here `messages[0]` is the newest message,
+    /// so the chat is the reverse of what [`ChatSplitter::try_split`] takes:
+    /// this gives the same groups as splitting the reversed chat,
+    /// but reversed too
+    /// and
+    /// in the opposite order,
+    /// without reversing the messages themselves.
+    /// Likewise,
+    /// pinned messages,
+    /// e.g.,
+    /// a system prompt at the end of `messages`
+    /// (see [`ChatSplitter::pin_system_prompt`]),
+    /// are in neither of the returned slices.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`ChatSplitter::try_split`].
+    #[inline]
+    pub fn try_split_reversed<'a, M>(
+        &self,
+        messages: &'a [M],
+    ) -> Result<(&'a [M], &'a [M]), SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        // Only references are reversed,
+        // never the messages themselves.
+        let reversed: Vec<_> = messages.iter().rev().collect();
+        let outdated = self.outdated(&reversed)?;
+        let (start, end) = (
+            messages.len() - outdated.end,
+            messages.len() - outdated.start,
+        );
+        Ok((&messages[..start], &messages[start..end]))
+    }
+
+    /// Split the chat into three groups of messages,
+    /// the 'head',
+    /// the 'outdated' and the
+    /// 'recent' ones.
+    ///
+    /// This is the same as [`ChatSplitter::try_split_three`],
+    /// but panics instead of returning an error.
+    ///
+    /// # Panics
+    ///
+    /// If [`ChatSplitter::try_split_three`] fails.
+    #[inline]
+    pub fn split_three<'a, M>(&self, messages: &'a [M]) -> (&'a [M], &'a [M], &'a [M])
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        self.try_split_three(messages).unwrap()
+    }
+
+    /// Split the chat into three groups of messages,
+    /// the 'head',
+    /// the 'outdated' and the
+    /// 'recent' ones.
+    ///
+    /// The 'head' contains the leading messages that are never 'outdated',
+    /// i.e.,
+    /// a pinned system prompt
+    /// (see [`ChatSplitter::pin_system_prompt`])
+    /// the head kept when trimming the middle
+    /// (see [`ChatSplitter::trim_mode`])
+    /// or the first messages always kept
+    /// (see [`ChatSplitter::keep_first`]),
+    /// and is empty otherwise.
+    /// The 'head' and 'recent' messages together are guaranteed to satisfy
+    /// the given limits,
+    /// while
+    /// the 'outdated' ones contain all the ones in between.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`ChatSplitter::try_split`].
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    pub fn try_split_three<'a, M>(
+        &self,
+        messages: &'a [M],
+    ) -> Result<(&'a [M], &'a [M], &'a [M]), SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        let (pinned, outdated, recent) = self.try_split_ranges(messages)?;
+        Ok((&messages[pinned], &messages[outdated], &messages[recent]))
+    }
+
+    /// Get the 'recent' messages of the chat,
+    /// borrowing them whenever possible.
+    ///
+    /// This is the same as [`ChatSplitter::try_recent_cow`],
+    /// but panics instead of returning an error.
+    ///
+    /// # Panics
+    ///
+    /// If [`ChatSplitter::try_recent_cow`] fails.
+    #[inline]
+    pub fn recent_cow<'a, M>(&self, messages: &'a [M]) -> Cow<'a, [M]>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        self.try_recent_cow(messages).unwrap()
+    }
+
+    /// Get the 'recent' messages of the chat,
+    /// borrowing them whenever possible.
+    ///
+    /// Like in [`ChatSplitter::try_split_owned`],
+    /// pinned messages are part of 'recent',
+    /// but messages are only cloned if they are not contiguous,
+    /// i.e.,
+    /// if messages between pinned and 'recent' ones are 'outdated'.
+    /// In particular,
+    /// nothing is cloned,
+    /// and
+    /// nothing is allocated for the messages,
+    /// if the whole chat fits.
+    /// Unlike [`ChatSplitter::try_split_owned`],
+    /// the last message is never truncated.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`ChatSplitter::try_split`].
+    #[inline]
+    pub fn try_recent_cow<'a, M>(&self, messages: &'a [M]) -> Result<Cow<'a, [M]>, SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        let outdated = self.outdated(messages)?;
+        Ok(if outdated.is_empty() {
+            Cow::Borrowed(messages)
+        } else if outdated.start == 0 {
+            Cow::Borrowed(&messages[outdated.end..])
+        } else {
+            Cow::Owned([&messages[..outdated.start], &messages[outdated.end..]].concat())
+        })
+    }
+
+    /// Split the chat into two groups of messages,
+    /// along with statistics about the split.
+    ///
+    /// This is the same as [`ChatSplitter::try_split_with_stats`],
+    /// but panics instead of returning an error.
+    ///
+    /// # Panics
+    ///
+    /// If [`ChatSplitter::try_split_with_stats`] fails.
+    #[inline]
+    pub fn split_with_stats<'a, M>(&self, messages: &'a [M]) -> (&'a [M], &'a [M], SplitStats)
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        self.try_split_with_stats(messages).unwrap()
+    }
+
+    /// Split the chat into two groups of messages,
+    /// along with statistics about the split.
+    ///
+    /// The messages are the same as returned by [`ChatSplitter::try_split`],
+    /// while
+    /// the statistics tell how many messages and input tokens each group
+    /// takes,
+    /// which is useful for tuning the limits from production data.
+    /// The statistics are also logged at the info level.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`ChatSplitter::try_split`].
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    pub fn try_split_with_stats<'a, M>(
+        &self,
+        messages: &'a [M],
+    ) -> Result<(&'a [M], &'a [M], SplitStats), SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        let (plan, summary) = self.summary(messages)?;
+        let stats = SplitStats {
+            outdated_messages: plan.outdated_messages,
+            recent_messages: messages.len() - plan.outdated_messages,
+            outdated_tokens: summary.outdated_tokens,
+            recent_tokens: summary.recent_tokens,
+            binding_constraint: plan.binding_constraint(),
+        };
+        log::info!(
+            "outdated_messages = {}, recent_messages = {}, outdated_tokens = {}, recent_tokens = {}, binding_constraint = {:?}",
+            stats.outdated_messages,
+            stats.recent_messages,
+            stats.outdated_tokens,
+            stats.recent_tokens,
+            stats.binding_constraint
+        );
+        let (outdated, recent) = plan.split(messages);
+        Ok((outdated, recent, stats))
+    }
+
+    /// Split the chat into two groups of messages,
+    /// without blocking the asynchronous runtime.
+    ///
+    /// This is the same as [`ChatSplitter::try_split_async`],
+    /// but panics instead of returning an error.
+    ///
+    /// # Panics
+    ///
+    /// If [`ChatSplitter::try_split_async`] fails.
+    #[cfg(feature = "tokio")]
+    #[inline]
+    pub async fn split_async<'a, M>(&self, messages: &'a [M]) -> (&'a [M], &'a [M])
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        self.try_split_async(messages).await.unwrap()
+    }
+
+    /// Split the chat into two groups of messages,
+    /// without blocking the asynchronous runtime.
+    ///
+    /// This is the same as [`ChatSplitter::try_split`],
+    /// but tokenization runs on [`tokio::task::spawn_blocking`],
+    /// which keeps the runtime responsive while splitting large chats.
+    /// Only the messages converted for tokenization are sent there,
+    /// so the messages themselves need not be [`Send`].
+    ///
+    /// # Errors
+    ///
+    /// The same as [`ChatSplitter::try_split`].
+    #[cfg(feature = "tokio")]
+    #[inline]
+    pub async fn try_split_async<'a, M>(
+        &self,
+        messages: &'a [M],
+    ) -> Result<(&'a [M], &'a [M]), SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        let converted = try_into_tiktoken_rs(messages)?;
+        let image_tokens = messages
+            .iter()
+            .enumerate()
+            .map(|(index, message)| self.message_image_tokens(index, message))
+            .collect::<Result<Vec<_>, _>>()?;
+        let splitter = self.clone();
+        let plan = tokio::task::spawn_blocking(move || {
+            if !splitter.strategy.uses_tokens() {
+                return splitter.plan_with_tokens(&converted, None);
+            }
+            let mut tokens = splitter.count_tiktoken_rs_message_tokens(&converted)?;
+            for (tokens, image_tokens) in tokens.iter_mut().zip(image_tokens) {
+                *tokens += image_tokens;
+            }
+            splitter.plan_with_tokens(&converted, Some(&tokens))
+        })
+        .await
+        .unwrap_or_else(|error| std::panic::resume_unwind(error.into_panic()))?;
+        Ok(plan.split(messages))
+    }
+
+    /// Split the chat into two groups of messages,
+    /// using the given `max_tokens` for this call only.
+    ///
+    /// This is the same as [`ChatSplitter::try_split_with_max_tokens`],
+    /// but panics instead of returning an error.
+    ///
+    /// # Panics
+    ///
+    /// If [`ChatSplitter::try_split_with_max_tokens`] fails.
+    #[inline]
+    pub fn split_with_max_tokens<'a, M>(
+        &self,
+        messages: &'a [M],
+        max_tokens: impl Into<u32>,
+    ) -> (&'a [M], &'a [M])
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        self.try_split_with_max_tokens(messages, max_tokens)
+            .unwrap()
+    }
+
+    /// Split the chat into two groups of messages,
+    /// using the given `max_tokens` for this call only.
+    ///
+    /// This is the same as [`ChatSplitter::try_split`],
+    /// but `max_tokens` overrides [`ChatSplitter::max_tokens`],
+    /// leaving the configured value untouched.
+    /// This is useful when the completion budget varies per request.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`ChatSplitter::try_split`].
+    #[inline]
+    pub fn try_split_with_max_tokens<'a, M>(
+        &self,
+        messages: &'a [M],
+        max_tokens: impl Into<u32>,
+    ) -> Result<(&'a [M], &'a [M]), SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        self.clone().max_tokens(max_tokens).try_split(messages)
+    }
+
+    /// Split the chat into two groups of messages,
+    /// fitting 'recent' into the given input token budget.
+    ///
+    /// This is the same as [`ChatSplitter::try_split_by_budget`],
+    /// but panics instead of returning an error.
+    ///
+    /// # Panics
+    ///
+    /// If [`ChatSplitter::try_split_by_budget`] fails.
+    #[inline]
+    pub fn split_by_budget<'a, M>(
+        &self,
+        messages: &'a [M],
+        input_budget: usize,
+    ) -> (&'a [M], &'a [M])
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        self.try_split_by_budget(messages, input_budget).unwrap()
+    }
+
+    /// Split the chat into two groups of messages,
+    /// fitting 'recent' into the given input token budget.
+    ///
+    /// This is the same as [`ChatSplitter::try_split`],
+    /// but 'recent' messages take at most `input_budget` input tokens,
+    /// regardless of [`ChatSplitter::max_tokens`],
+    /// [`ChatSplitter::max_input_tokens`],
+    /// [`ChatSplitter::reserve_tokens`],
+    /// [`ChatSplitter::safety_margin_tokens`] and the context size.
+    /// This is useful when tokens are reserved elsewhere,
+    /// e.g.,
+    /// for tools.
+    /// All message limits still apply.
+    ///
+    /// # Errors
+    ///
+    /// If tokenizer for the specified model is not found or is not a supported
+    /// chat model
+    /// ([`SplitError::UnknownModel`]),
+    /// or if some message cannot be converted for tokenization
+    /// ([`SplitError::UnsupportedRole`]).
+    #[inline]
+    pub fn try_split_by_budget<'a, M>(
+        &self,
+        messages: &'a [M],
+        input_budget: usize,
+    ) -> Result<(&'a [M], &'a [M]), SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        let mut splitter = self.clone();
+        splitter.input_budget = Some(input_budget);
+        splitter.try_split(messages)
+    }
+
+    /// Split the chat into two groups of messages,
+    /// using the token counts the messages report themselves.
+    ///
+    /// This is the same as [`ChatSplitter::try_split_counted`],
+    /// but panics instead of returning an error.
+    ///
+    /// # Panics
+    ///
+    /// If [`ChatSplitter::try_split_counted`] fails.
+    #[inline]
+    pub fn split_counted<'a, M>(&self, messages: &'a [M]) -> (&'a [M], &'a [M])
+    where
+        M: TokenCounted + IntoChatCompletionRequestMessage + Clone,
+    {
+        self.try_split_counted(messages).unwrap()
+    }
+
+    /// Split the chat into two groups of messages,
+    /// using the token counts the messages report themselves
+    /// (see [`TokenCounted`]).
+    ///
+    /// This is the same as [`ChatSplitter::try_split`],
+    /// pinned messages and all,
+    /// but messages are never tokenized,
+    /// which is useful when they already carry token counts from elsewhere.
+    /// Only the per-reply overhead is counted with the configured tokenizer
+    /// (see [`ChatSplitter::token_overhead`]).
+    ///
+    /// # Errors
+    ///
+    /// If tokenizer for the specified model is not found or is not a supported
+    /// chat model
+    /// ([`SplitError::UnknownModel`]),
+    /// if some message cannot be converted
+    /// ([`SplitError::UnsupportedRole`]),
+    /// or if [`ChatSplitter::max_tokens`] is not smaller than the context size
+    /// ([`SplitError::CompletionBudgetTooLarge`]).
+    #[inline]
+    pub fn try_split_counted<'a, M>(
+        &self,
+        messages: &'a [M],
+    ) -> Result<(&'a [M], &'a [M]), SplitError>
+    where
+        M: TokenCounted + IntoChatCompletionRequestMessage + Clone,
+    {
+        let tokens = self.strategy.uses_tokens().then(|| {
+            let model = self.counting_model();
+            messages
+                .iter()
+                .map(|message| message.token_count(model))
+                .collect::<Vec<_>>()
+        });
+        Ok(self
+            .plan_with_tokens(messages, tokens.as_deref())?
+            .split(messages))
+    }
+
+    /// Split the chat into two groups of messages,
+    /// calling `on_outdated` for each 'outdated' message.
+    ///
+    /// This is the same as [`ChatSplitter::try_split_with`],
+    /// but panics instead of returning an error.
+    ///
+    /// # Panics
+    ///
+    /// If [`ChatSplitter::try_split_with`] fails.
+    #[inline]
+    pub fn split_with<'a, M, F>(&self, messages: &'a [M], on_outdated: F) -> (&'a [M], &'a [M])
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+        F: FnMut(&M),
+    {
+        self.try_split_with(messages, on_outdated).unwrap()
+    }
+
+    /// Split the chat into two groups of messages,
+    /// calling `on_outdated` for each 'outdated' message.
+    ///
+    /// This is the same as [`ChatSplitter::try_split`],
+    /// but `on_outdated` is called for every 'outdated' message,
+    /// in order,
+    /// e.g.,
+    /// to summarize or persist them before they are discarded.
+    ///
+    /// # Errors
+    ///
+    /// If tokenizer for the specified model is not found or is not a supported
+    /// chat model
+    /// ([`SplitError::UnknownModel`]),
+    /// if some message cannot be converted for tokenization
+    /// ([`SplitError::UnsupportedRole`]),
+    /// or if [`ChatSplitter::max_tokens`] is not smaller than the context size
+    /// ([`SplitError::CompletionBudgetTooLarge`]).
+    /// In this case,
+    /// `on_outdated` is never called.
+    #[inline]
+    pub fn try_split_with<'a, M, F>(
+        &self,
+        messages: &'a [M],
+        on_outdated: F,
+    ) -> Result<(&'a [M], &'a [M]), SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+        F: FnMut(&M),
+    {
+        let (outdated, recent) = self.try_split(messages)?;
+        outdated.iter().for_each(on_outdated);
+        Ok((outdated, recent))
+    }
+
+    /// Iterate over progressively smaller 'recent' windows.
+    ///
+    /// This is the same as [`ChatSplitter::try_windows`],
+    /// but panics instead of returning an error.
+    ///
+    /// # Panics
+    ///
+    /// If [`ChatSplitter::try_windows`] fails.
+    #[inline]
+    pub fn windows<'a, M>(&self, messages: &'a [M]) -> impl Iterator<Item = &'a [M]>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        self.try_windows(messages).unwrap()
+    }
+
+    /// Iterate over progressively smaller 'recent' windows.
+    ///
+    /// The first window is the 'recent' slice returned by
+    /// [`ChatSplitter::try_split`],
+    /// each following window is one message shorter
+    /// (dropping the oldest one),
+    /// down to the empty window.
+    /// This is useful for backing off when the API still rejects a request.
+    /// If [`ChatSplitter::keep_pairs`] is set,
+    /// windows starting with an `assistant` message are skipped,
+    /// and
+    /// if [`ChatSplitter::keep_tool_groups`] is set,
+    /// windows starting with a `tool` message are skipped.
+    /// Likewise,
+    /// if [`ChatSplitter::snap_to_turns`] is set,
+    /// windows not starting with a `user` message are skipped.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`ChatSplitter::try_split`].
+    #[inline]
+    pub fn try_windows<'a, M>(
+        &self,
+        messages: &'a [M],
+    ) -> Result<impl Iterator<Item = &'a [M]>, SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        let start = self.outdated(messages)?.end;
+        let skipped = messages[start..]
+            .iter()
+            .map(|message| {
+                Ok(self.is_orphan(message)? || self.snap_to_turns && role(message)? != "user")
+            })
+            .collect::<Result<Vec<_>, SplitError>>()?;
+
+        Ok((start..=messages.len())
+            .filter(move |&n| n == start || !skipped.get(n - start).copied().unwrap_or(false))
+            .map(move |n| &messages[n..]))
+    }
+
+    /// Split a chat made of groups of messages into two groups of groups,
+    /// the 'outdated' and the
+    /// 'recent' ones.
+    ///
+    /// This is the same as [`ChatSplitter::try_split_groups`],
+    /// but panics instead of returning an error.
+    ///
+    /// # Panics
+    ///
+    /// If [`ChatSplitter::try_split_groups`] fails.
+    #[inline]
+    pub fn split_groups<'a, M>(&self, groups: &'a [Vec<M>]) -> (&'a [Vec<M>], &'a [Vec<M>])
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        self.try_split_groups(groups).unwrap()
+    }
+
+    /// Split a chat made of groups of messages into two groups of groups,
+    /// the 'outdated' and the
+    /// 'recent' ones.
+    ///
+    /// Each group is indivisible,
+    /// e.g.,
+    /// a set of retrieved documents,
+    /// and is never cut between 'outdated' and 'recent':
+    /// if the split position of the flattened chat falls inside a group,
+    /// the whole group is 'outdated'.
+    /// Groups are rounded off after the split is planned,
+    /// so they win over [`ChatSplitter::min_messages`]:
+    /// 'recent' may end up with fewer messages than that.
+    /// In particular,
+    /// if the last group alone exceeds the limits,
+    /// 'recent' is empty.
+    ///
+    /// Groups containing pinned messages
+    /// (see [`ChatSplitter::try_split_position`])
+    /// are never 'outdated',
+    /// even if only partially pinned,
+    /// but are in neither of the returned slices,
+    /// just as pinned messages in [`ChatSplitter::try_split`].
+    ///
+    /// # Errors
+    ///
+    /// The same as [`ChatSplitter::try_split`].
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    pub fn try_split_groups<'a, M>(
+        &self,
+        groups: &'a [Vec<M>],
+    ) -> Result<(&'a [Vec<M>], &'a [Vec<M>]), SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        let messages: Vec<&M> = groups.iter().flatten().collect();
+        let outdated = self.outdated(&messages)?;
+
+        // Number of messages up to the end of each group.
+        let ends: Vec<_> = groups
+            .iter()
+            .scan(0, |end, group| {
+                *end += group.len();
+                Some(*end)
+            })
+            .collect();
+        // Number of groups up to the one containing the message before
+        // `position`.
+        let groups_before = |position: usize| match position {
+            0 => 0,
+            position => ends.partition_point(|&end| end < position) + 1,
+        };
+        let first = groups_before(outdated.start);
+        let last = groups_before(outdated.end).max(first);
+        Ok((&groups[first..last], &groups[last..]))
+    }
+
+    /// Split the chat into two owned groups of messages,
+    /// the 'outdated' and the
+    /// 'recent' ones.
+    ///
+    /// This is the same as [`ChatSplitter::try_split_owned`],
+    /// but panics instead of returning an error.
+    ///
+    /// # Panics
+    ///
+    /// If [`ChatSplitter::try_split_owned`] fails.
+    #[inline]
+    pub fn split_owned<M>(&self, messages: Vec<M>) -> (Vec<M>, Vec<M>)
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        self.try_split_owned(messages).unwrap()
+    }
+
+    /// Split the chat into two owned groups of messages,
+    /// the 'outdated' and the
+    /// 'recent' ones.
+    ///
+    /// This is the same as [`ChatSplitter::try_split`],
+    /// but consumes the messages and moves them into the returned groups
+    /// instead of borrowing.
+    /// A pinned system prompt is always the first 'recent' message,
+    /// and so are the other pinned messages
+    /// (see [`ChatSplitter::try_split_three`]).
+    /// The last message might be truncated to fit
+    /// (see [`ChatSplitter::truncate_oversized`]).
+    ///
+    /// # Errors
+    ///
+    /// The same as [`ChatSplitter::try_split`].
+    #[inline]
+    pub fn try_split_owned<M>(&self, mut messages: Vec<M>) -> Result<(Vec<M>, Vec<M>), SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        if self.truncate_oversized {
+            self.truncate_last(&mut messages)?;
+        }
+        let outdated = self.outdated(&messages)?;
+        let mut recent = messages.split_off(outdated.end);
+        let outdated = messages.split_off(outdated.start);
+        messages.append(&mut recent);
+        Ok((outdated, messages))
+    }
+
+    /// Get the 'recent' messages of the chat,
+    /// converted to [`async_openai` chat completion request message
+    /// type](`async_openai::types::ChatCompletionRequestMessage`).
+    ///
+    /// This is the same as [`ChatSplitter::try_recent_async_openai`],
+    /// but panics instead of returning an error.
+    ///
+    /// # Panics
+    ///
+    /// If [`ChatSplitter::try_recent_async_openai`] fails.
+    #[inline]
+    #[cfg(feature = "async-openai")]
+    pub fn recent_async_openai<M>(
+        &self,
+        messages: &[M],
+    ) -> Vec<async_openai::types::ChatCompletionRequestMessage>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        self.try_recent_async_openai(messages).unwrap()
+    }
+
+    /// Get the 'recent' messages of the chat,
+    /// converted to [`async_openai` chat completion request message
+    /// type](`async_openai::types::ChatCompletionRequestMessage`),
+    /// ready to be sent in a request.
+    ///
+    /// These are the 'recent' messages of [`ChatSplitter::try_split_owned`],
+    /// so pinned messages are included
+    /// and
+    /// the last message might be truncated to fit.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`ChatSplitter::try_split`].
+    #[inline]
+    #[cfg(feature = "async-openai")]
+    pub fn try_recent_async_openai<M>(
+        &self,
+        messages: &[M],
+    ) -> Result<Vec<async_openai::types::ChatCompletionRequestMessage>, SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        let (_outdated, recent) = self.try_split_owned(messages.to_vec())?;
+        Ok(try_into_async_openai_vec(recent)?)
+    }
+
+    /// Get the 'outdated' messages of the chat,
+    /// converted to [`async_openai` chat completion request message
+    /// type](`async_openai::types::ChatCompletionRequestMessage`).
+    ///
+    /// This is the same as [`ChatSplitter::try_outdated_async_openai`],
+    /// but panics instead of returning an error.
+    ///
+    /// # Panics
+    ///
+    /// If [`ChatSplitter::try_outdated_async_openai`] fails.
+    #[inline]
+    #[cfg(feature = "async-openai")]
+    pub fn outdated_async_openai<M>(
+        &self,
+        messages: &[M],
+    ) -> Vec<async_openai::types::ChatCompletionRequestMessage>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        self.try_outdated_async_openai(messages).unwrap()
+    }
+
+    /// Get the 'outdated' messages of the chat,
+    /// converted to [`async_openai` chat completion request message
+    /// type](`async_openai::types::ChatCompletionRequestMessage`),
+    /// ready to be archived or summarized.
+    ///
+    /// These are the 'outdated' messages of [`ChatSplitter::try_split_owned`],
+    /// so they always complement the ones of
+    /// [`ChatSplitter::try_recent_async_openai`].
+    ///
+    /// # Errors
+    ///
+    /// The same as [`ChatSplitter::try_split`].
+    #[inline]
+    #[cfg(feature = "async-openai")]
+    pub fn try_outdated_async_openai<M>(
+        &self,
+        messages: &[M],
+    ) -> Result<Vec<async_openai::types::ChatCompletionRequestMessage>, SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        let (outdated, _recent) = self.try_split_owned(messages.to_vec())?;
+        Ok(try_into_async_openai_vec(outdated)?)
+    }
+
+    /// Split the chat into two owned groups of messages,
+    /// with a system prompt prepended to the 'recent' ones.
+    ///
+    /// This is the same as [`ChatSplitter::try_split_with_system`],
+    /// but panics instead of returning an error.
+    ///
+    /// # Panics
+    ///
+    /// If [`ChatSplitter::try_split_with_system`] fails.
+    #[inline]
+    pub fn split_with_system<M>(&self, system: M, messages: &[M]) -> (Vec<M>, Vec<M>)
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        self.try_split_with_system(system, messages).unwrap()
+    }
+
+    /// Split the chat into two owned groups of messages,
+    /// the 'outdated' and the
+    /// 'recent' ones,
+    /// with the given system prompt prepended to the 'recent' ones.
+    ///
+    /// The system prompt counts towards the limits,
+    /// as if reserved with [`ChatSplitter::try_reserve_prefix`]
+    /// on top of any prefix already reserved,
+    /// so that the 'recent' messages still fit with it.
+    /// If [`ChatSplitter::dedupe_system`] is enabled,
+    /// stored system messages matching the system prompt by exact content
+    /// are 'outdated',
+    /// as in [`ChatSplitter::try_split_retaining`].
+    ///
+    /// # Errors
+    ///
+    /// The same as [`ChatSplitter::try_split`].
+    #[inline]
+    pub fn try_split_with_system<M>(
+        &self,
+        system: M,
+        messages: &[M],
+    ) -> Result<(Vec<M>, Vec<M>), SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        let (prefix_messages, prefix_tokens) = self.prefix;
+        let mut splitter = self.clone().try_reserve_prefix(slice::from_ref(&system))?;
+        splitter.prefix.0 += prefix_messages;
+        splitter.prefix.1 += prefix_tokens;
+
+        let prompt = system.clone().try_into_tiktoken_rs()?;
+        let is_duplicate = |message: &M| {
+            self.dedupe_system
+                && message.clone().try_into_tiktoken_rs().is_ok_and(|message| {
+                    is_system_role(&message.role) && message.content == prompt.content
+                })
+        };
+        let (outdated, recent) =
+            splitter.try_split_retaining(messages, |message| !is_duplicate(message))?;
+        Ok((outdated, iter::once(system).chain(recent).collect()))
+    }
+
+    /// Split the chat into two owned groups of messages,
+    /// after dropping some of them.
+    ///
+    /// This is the same as [`ChatSplitter::try_split_retaining`],
+    /// but panics instead of returning an error.
+    ///
+    /// # Panics
+    ///
+    /// If [`ChatSplitter::try_split_retaining`] fails.
+    #[inline]
+    pub fn split_retaining<M, F>(&self, messages: &[M], keep: F) -> (Vec<M>, Vec<M>)
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+        F: Fn(&M) -> bool,
+    {
+        self.try_split_retaining(messages, keep).unwrap()
+    }
+
+    /// Split the chat into two owned groups of messages,
+    /// the 'outdated' and the
+    /// 'recent' ones,
+    /// after dropping the messages for which `keep` returns `false`,
+    /// e.g.,
+    /// old tool results.
+    ///
+    /// Only the kept messages are tokenized and split,
+    /// and
+    /// only they can be 'recent',
+    /// including pinned ones,
+    /// just like in [`ChatSplitter::try_split_owned`].
+    /// The 'outdated' messages contain all the others,
+    /// including the dropped ones,
+    /// so that both groups together always contain every message.
+    /// Both keep the original order.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`ChatSplitter::try_split`].
+    #[inline]
+    pub fn try_split_retaining<M, F>(
+        &self,
+        messages: &[M],
+        keep: F,
+    ) -> Result<(Vec<M>, Vec<M>), SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+        F: Fn(&M) -> bool,
+    {
+        let (indices, retained): (Vec<_>, Vec<_>) = messages
+            .iter()
+            .enumerate()
+            .filter(|(_, message)| keep(message))
+            .map(|(index, message)| (index, message.clone()))
+            .unzip();
+        let outdated = self.outdated(&retained)?;
+
+        let mut is_recent = vec![false; messages.len()];
+        for &index in indices[..outdated.start]
+            .iter()
+            .chain(&indices[outdated.end..])
+        {
+            is_recent[index] = true;
+        }
+        let (recent, outdated): (Vec<_>, Vec<_>) = messages
+            .iter()
+            .zip(is_recent)
+            .partition(|(_, is_recent)| *is_recent);
+        Ok((
+            outdated
+                .into_iter()
+                .map(|(message, _)| message.clone())
+                .collect(),
+            recent
+                .into_iter()
+                .map(|(message, _)| message.clone())
+                .collect(),
+        ))
+    }
+
+    /// Split many independent chats into two owned groups of messages each.
+    ///
+    /// This is the same as [`ChatSplitter::try_split_batch`],
+    /// but panics instead of returning an error.
+    ///
+    /// # Panics
+    ///
+    /// If [`ChatSplitter::try_split_batch`] fails.
+    #[inline]
+    pub fn split_batch<M>(&self, conversations: &[Vec<M>]) -> Vec<(Vec<M>, Vec<M>)>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        self.try_split_batch(conversations).unwrap()
+    }
+
+    /// Split many independent chats into two owned groups of messages each,
+    /// the 'outdated' and the
+    /// 'recent' ones.
+    ///
+    /// This is the same as calling [`ChatSplitter::try_split_owned`] on
+    /// clones of each chat,
+    /// in the same order,
+    /// but all messages are tokenized at once,
+    /// so the tokenizer is set up only once per call.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`ChatSplitter::try_split`].
+    #[inline]
+    #[allow(clippy::type_complexity)]
+    pub fn try_split_batch<M>(
+        &self,
+        conversations: &[Vec<M>],
+    ) -> Result<Vec<(Vec<M>, Vec<M>)>, SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        if self.truncate_oversized || !self.strategy.uses_tokens() {
+            // Truncation changes the tokens of the last messages,
+            // and
+            // without tokens there is nothing to share.
+            return conversations
+                .iter()
+                .map(|messages| self.try_split_owned(messages.clone()))
+                .collect();
+        }
+
+        let converted = conversations
+            .iter()
+            .map(|messages| try_into_tiktoken_rs(messages))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut tokens = self
+            .using_tokenizer(|tokenizer| tokenizer.count_message_tokens(&converted.concat()))?
+            .into_iter();
+
+        conversations
+            .iter()
+            .zip(&converted)
+            .map(|(messages, converted)| {
+                let tokens = tokens.by_ref().take(messages.len()).collect();
+                let mut tokens = self.adjust_message_tokens(converted, tokens)?;
+                for (index, (tokens, message)) in tokens.iter_mut().zip(messages).enumerate() {
+                    *tokens += self.message_image_tokens(index, message)?;
+                }
+
+                let outdated = self.plan_with_tokens(messages, Some(&tokens))?.outdated();
+                let recent = [&messages[..outdated.start], &messages[outdated.end..]].concat();
+                Ok((messages[outdated].to_vec(), recent))
+            })
+            .collect()
+    }
+
+    /// Remove the 'outdated' messages from the chat,
+    /// returning them.
+    ///
+    /// This is the same as [`ChatSplitter::try_prune_in_place`],
+    /// but panics instead of returning an error.
+    ///
+    /// # Panics
+    ///
+    /// If [`ChatSplitter::try_prune_in_place`] fails.
+    #[inline]
+    pub fn prune_in_place<M>(&self, messages: &mut Vec<M>) -> Vec<M>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        self.try_prune_in_place(messages).unwrap()
+    }
+
+    /// Remove the 'outdated' messages from the chat,
+    /// returning them.
+    ///
+    /// This is the same as [`ChatSplitter::try_split_owned`]
+    /// followed by assigning 'recent' back to `messages`,
+    /// but without moving the 'recent' messages to a new vector,
+    /// which is useful for long-running sessions.
+    /// Pinned messages are kept,
+    /// and
+    /// the last message might be truncated to fit
+    /// (see [`ChatSplitter::truncate_oversized`]).
+    ///
+    /// # Errors
+    ///
+    /// The same as [`ChatSplitter::try_split`].
+    #[inline]
+    pub fn try_prune_in_place<M>(&self, messages: &mut Vec<M>) -> Result<Vec<M>, SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        if self.truncate_oversized {
+            self.truncate_last(messages)?;
+        }
+        let outdated = self.outdated(messages)?;
+        Ok(messages.drain(outdated).collect())
+    }
+
+    /// Append new messages to the chat history,
+    /// then split the chat into two owned groups of messages,
+    /// the 'outdated' and the
+    /// 'recent' ones.
+    ///
+    /// This is the same as [`ChatSplitter::try_split_appending`],
+    /// but panics instead of returning an error.
+    ///
+    /// # Panics
+    ///
+    /// If [`ChatSplitter::try_split_appending`] fails.
+    #[inline]
+    pub fn split_appending<M>(&self, history: &[M], new: &[M]) -> (Vec<M>, Vec<M>)
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        self.try_split_appending(history, new).unwrap()
+    }
+
+    /// Append new messages to the chat history,
+    /// then split the chat into two owned groups of messages,
+    /// the 'outdated' and the
+    /// 'recent' ones.
+    ///
+    /// This is the same as [`ChatSplitter::try_split_owned`] on the
+    /// concatenated messages,
+    /// which is the usual way of preparing a request,
+    /// e.g.,
+    /// with a new `user` message.
+    /// Since the latest messages are always the last to become 'outdated',
+    /// the new messages are 'recent' whenever they fit the limits at all;
+    /// otherwise,
+    /// a warning is logged.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`ChatSplitter::try_split`].
+    #[inline]
+    pub fn try_split_appending<M>(
+        &self,
+        history: &[M],
+        new: &[M],
+    ) -> Result<(Vec<M>, Vec<M>), SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        let (outdated, recent) = self.try_split_owned([history, new].concat())?;
+        if recent.len() < new.len() {
+            log::warn!("new messages = {} > {}", new.len(), recent.len());
+        }
+        Ok((outdated, recent))
+    }
+
+    /// Truncate the content of the last message,
+    /// if it alone exceeds the token limits
+    /// (see [`ChatSplitter::truncate_oversized`]).
+    ///
+    /// # Errors
+    ///
+    /// If tokenizer for the specified model is not found or is not a supported
+    /// chat model,
+    /// if some message cannot be converted for tokenization,
+    /// or if [`ChatSplitter::max_tokens`] is not smaller than the context size.
+    #[inline]
+    fn truncate_last<M>(&self, messages: &mut [M]) -> Result<(), SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+    {
+        if !self.strategy.uses_tokens() {
+            return Ok(());
+        }
+        let (pinned, messages) = messages.split_at_mut(self.pinned(messages)?);
+        let Some(last) = messages.last_mut() else {
+            return Ok(());
+        };
+
+        // Images are never truncated,
+        // so only the converted messages are counted again.
+        let budget = self.input_token_limit()?.saturating_sub(
+            self.count_image_tokens(pinned)? + self.message_image_tokens(pinned.len(), last)?,
+        );
+        let mut converted = try_into_tiktoken_rs(pinned)?;
+        converted.push(last.clone().try_into_tiktoken_rs()?);
+        let tokens = self.count_tiktoken_rs_tokens(&converted)?;
+        if tokens <= budget {
+            return Ok(());
+        }
+        let Some(content) = last.content_mut() else {
+            return Ok(());
+        };
+        let fits = |converted: &[tiktoken_rs::ChatCompletionRequestMessage]| {
+            Ok::<_, SplitError>(self.count_tiktoken_rs_tokens(converted)? <= budget)
+        };
+        let index = pinned.len();
+
+        // Drop the excess tokens from the end of the content,
+        // cutting only at character boundaries.
+        let mut len = None;
+        if let Some(bpe) = self.bpe() {
+            let encoded = bpe.encode_with_special_tokens(content);
+            let kept =
+                bpe._decode_native(&encoded[..encoded.len().saturating_sub(tokens - budget)]);
+            let cut =
+                str::from_utf8(&kept).map_or_else(|error| error.valid_up_to(), |kept| kept.len());
+            converted[index].content = Some(content[..cut].to_owned());
+            if fits(&converted)? {
+                len = Some(cut);
+            }
+        }
+
+        // Otherwise,
+        // e.g.,
+        // for custom tokenizers,
+        // find the longest prefix that fits.
+        let len = match len {
+            Some(len) => len,
+            None => {
+                let boundaries: Vec<_> = content
+                    .char_indices()
+                    .map(|(index, _)| index)
+                    .chain([content.len()])
+                    .collect();
+                let (mut low, mut high) = (0, boundaries.len());
+                while low < high {
+                    let mid = low + (high - low) / 2;
+                    converted[index].content = Some(content[..boundaries[mid]].to_owned());
+                    if fits(&converted)? {
+                        low = mid + 1;
+                    } else {
+                        high = mid;
+                    }
+                }
+                low.checked_sub(1).map_or(0, |n| boundaries[n])
+            }
+        };
+        log::warn!("truncated message content = {} > {len}", content.len());
+        content.truncate(len);
+        Ok(())
+    }
+
+    /// Split a chat given as an iterator into two owned groups of messages,
+    /// the 'outdated' and the
+    /// 'recent' ones.
+    ///
+    /// This is the same as [`ChatSplitter::try_split_iter`],
+    /// but panics instead of returning an error.
+    ///
+    /// # Panics
+    ///
+    /// If [`ChatSplitter::try_split_iter`] fails.
+    #[inline]
+    pub fn split_iter<M, I>(&self, messages: I) -> (Vec<M>, Vec<M>)
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+        I: IntoIterator<Item = M>,
+    {
+        self.try_split_iter(messages).unwrap()
+    }
+
+    /// Split a chat given as an iterator into two owned groups of messages,
+    /// the 'outdated' and the
+    /// 'recent' ones.
+    ///
+    /// The messages are collected first,
+    /// so this is the same as [`ChatSplitter::try_split_owned`] on the
+    /// collected messages.
+    ///
+    /// # Errors
+    ///
+    /// The same as [`ChatSplitter::try_split`].
+    #[inline]
+    pub fn try_split_iter<M, I>(&self, messages: I) -> Result<(Vec<M>, Vec<M>), SplitError>
+    where
+        M: IntoChatCompletionRequestMessage + Clone,
+        I: IntoIterator<Item = M>,
+    {
+        self.try_split_owned(messages.into_iter().collect())
+    }
+}
+
+/// Builder of a [`ChatSplitter`] that validates all settings at once,
+/// as returned by [`ChatSplitter::builder`].
+///
+/// Unlike the setters of [`ChatSplitter`],
+/// which only log warnings,
+/// [`ChatSplitterBuilder::build`] fails on invalid settings.
+/// Settings not available here can be set on a [`ChatSplitter`] first,
+/// e.g.,
+/// `ChatSplitterBuilder::from(ChatSplitter::default().keep_pairs(true))`.
+#[derive(Clone, Debug, Default)]
+pub struct ChatSplitterBuilder {
+    /// The chat splitter being built.
+    splitter: ChatSplitter,
+}
+
+impl From<ChatSplitter> for ChatSplitterBuilder {
+    #[inline]
+    fn from(splitter: ChatSplitter) -> Self {
+        Self { splitter }
+    }
+}
+
+impl ChatSplitterBuilder {
+    /// Set the model to use
+    /// (see [`ChatSplitter::model`]).
+    #[inline]
+    #[must_use]
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.splitter = self.splitter.model(model);
+        self
+    }
+
+    /// Set the maximum number of tokens to leave for chat completion
+    /// (see [`ChatSplitter::max_tokens`]).
+    #[inline]
+    #[must_use]
+    pub fn max_tokens(mut self, max_tokens: impl Into<u32>) -> Self {
+        self.splitter.max_tokens = max_tokens.into();
+        self
+    }
+
+    /// Set the maximum number of input tokens
+    /// (see [`ChatSplitter::max_input_tokens`]).
+    #[inline]
+    #[must_use]
+    pub fn max_input_tokens(mut self, max_input_tokens: impl Into<u32>) -> Self {
+        self.splitter.max_input_tokens = Some(max_input_tokens.into());
+        self
+    }
+
+    /// Set the maximum number of messages to have in the chat
+    /// (see [`ChatSplitter::max_messages`]).
+    #[inline]
+    #[must_use]
+    pub fn max_messages(mut self, max_messages: impl Into<usize>) -> Self {
+        self.splitter.max_messages = max_messages.into();
+        self
+    }
+
+    /// Set the hard limit on the number of messages
+    /// (see [`ChatSplitter::message_limit`]).
+    #[inline]
+    #[must_use]
+    pub fn message_limit(mut self, message_limit: impl Into<usize>) -> Self {
+        self.splitter.message_limit = message_limit.into();
+        self
+    }
+
+    /// Set the minimum number of messages to have in the chat
+    /// (see [`ChatSplitter::min_messages`]).
+    #[inline]
+    #[must_use]
+    pub fn min_messages(mut self, min_messages: impl Into<usize>) -> Self {
+        self.splitter.min_messages = min_messages.into();
+        self
+    }
+
+    /// Use a custom [`Tokenizer`] for token counting
+    /// (see [`ChatSplitter::with_tokenizer`]).
+    #[inline]
+    #[must_use]
+    pub fn with_tokenizer(mut self, tokenizer: impl Tokenizer + 'static) -> Self {
+        self.splitter = self.splitter.with_tokenizer(tokenizer);
+        self
+    }
+
+    /// Validate all settings and build the [`ChatSplitter`].
+    ///
+    /// # Errors
+    ///
+    /// If tokenizer for the specified model is not found or is not a supported
+    /// chat model
+    /// ([`SplitError::UnknownModel`]),
+    /// if [`ChatSplitter::max_tokens`] is not smaller than the context size
+    /// ([`SplitError::CompletionBudgetTooLarge`]),
+    /// if [`ChatSplitter::max_messages`] exceeds
+    /// [`ChatSplitter::message_limit`]
+    /// ([`SplitError::MaxMessagesTooLarge`]),
+    /// or if [`ChatSplitter::min_messages`] exceeds
+    /// [`ChatSplitter::max_messages`]
+    /// ([`SplitError::MinMessagesTooLarge`]).
+    #[inline]
+    pub fn build(self) -> Result<ChatSplitter, SplitError> {
+        let splitter = self.splitter;
+        if splitter.tokenizer.is_none() && !is_chat_model(splitter.counting_model()) {
+            return Err(SplitError::UnknownModel(
+                splitter.counting_model().to_owned(),
+            ));
+        }
+        splitter.input_token_limit()?;
+        if splitter.max_messages > splitter.message_limit {
+            return Err(SplitError::MaxMessagesTooLarge {
+                max_messages: splitter.max_messages,
+                message_limit: splitter.message_limit,
+            });
+        }
+        if splitter.min_messages > splitter.max_messages {
+            return Err(SplitError::MinMessagesTooLarge {
+                min_messages: splitter.min_messages,
+                max_messages: splitter.max_messages,
+            });
+        }
+        Ok(splitter)
+    }
+}
+
+/// Get the runs of consecutive messages that are counted as one when
+/// coalescing
+/// (see [`ChatSplitter::coalesce_consecutive`]).
+#[inline]
+fn coalescing_runs(messages: &[tiktoken_rs::ChatCompletionRequestMessage]) -> Vec<Range<usize>> {
+    let coalescable = |message: &tiktoken_rs::ChatCompletionRequestMessage,
+                       next: &tiktoken_rs::ChatCompletionRequestMessage| {
+        message.function_call.is_none()
+            && next.function_call.is_none()
+            && message.role == next.role
+            && message.name == next.name
+    };
+
+    let mut runs: Vec<Range<usize>> = Vec::new();
+    for (i, message) in messages.iter().enumerate() {
+        match runs.last_mut() {
+            Some(run) if coalescable(&messages[run.end - 1], message) => run.end = i + 1,
+            _ => runs.push(i..i + 1),
+        }
+    }
+    runs
+}
+
+/// Merge consecutive messages into one,
+/// separating their contents by newlines.
+#[inline]
+fn coalesce(
+    messages: &[tiktoken_rs::ChatCompletionRequestMessage],
+) -> tiktoken_rs::ChatCompletionRequestMessage {
+    let mut coalesced = messages[0].clone();
+    for message in &messages[1..] {
+        let content = coalesced.content.get_or_insert_with(String::new);
+        content.push('\n');
+        content.push_str(message.content.as_deref().unwrap_or_default());
+    }
+    coalesced
+}
+
+/// Tests that only need [`tiktoken_rs`] messages,
+/// so that they also run without the `async-openai` feature.
+#[cfg(test)]
+mod tiktoken_tests {
+    use tiktoken_rs::num_tokens_from_messages;
+
+    use super::*;
+
+    fn message(
+        role: &str,
+        content: impl Into<String>,
+    ) -> tiktoken_rs::ChatCompletionRequestMessage {
+        tiktoken_rs::ChatCompletionRequestMessage {
+            role: role.to_owned(),
+            content: Some(content.into()),
+            ..Default::default()
+        }
+    }
+
+    /// Message type only implementing the required conversion,
+    /// as written by a crate not enabling the `async-openai` feature.
+    #[derive(Clone, Debug, PartialEq)]
+    struct Minimal(String);
+
+    impl IntoChatCompletionRequestMessage for Minimal {
+        fn into_tiktoken_rs(self) -> tiktoken_rs::ChatCompletionRequestMessage {
+            message("user", self.0)
+        }
+    }
+
+    #[test]
+    fn tiktoken_rs_messages_can_be_split() {
+        let mut messages = vec![message("system", "You are a helpful assistant.")];
+        messages.extend((0..8).map(|i| message("user", format!("Question number {i}?"))));
+
+        let splitter = ChatSplitter::default().max_messages(4_usize);
+        let (outdated, recent) = splitter.split(&messages);
+        assert_eq!(outdated, &messages[..5]);
+        assert_eq!(recent, &messages[5..]);
+
+        let splitter = splitter.pin_system_prompt(true);
+        let (outdated, recent) = splitter.split(&messages);
+        assert_eq!(outdated, &messages[1..6]);
+        assert_eq!(recent, &messages[6..]);
+    }
+
+    #[test]
+    fn tokens_are_counted_as_tiktoken_rs_does() {
+        let messages: Vec<_> = (0..4)
+            .map(|i| message("user", format!("Question number {i}?")))
+            .collect();
+        assert_eq!(
+            ChatSplitter::default().count_tokens(&messages),
+            Ok(num_tokens_from_messages("gpt-3.5-turbo", &messages).unwrap())
+        );
+    }
+
+    #[test]
+    fn messages_with_only_the_required_conversion_can_be_split() {
+        let messages: Vec<_> = (0..4)
+            .map(|i| Minimal(format!("Question number {i}?")))
+            .collect();
+        let (outdated, recent) = ChatSplitter::default()
+            .max_messages(3_usize)
+            .split(&messages);
+        assert_eq!(outdated, &messages[..1]);
+        assert_eq!(recent, &messages[1..]);
+
+        #[cfg(feature = "async-openai")]
+        assert_eq!(
+            messages[0].clone().into_async_openai(),
+            message("user", "Question number 0?").into_async_openai()
+        );
+    }
+}
+
+#[cfg(all(test, feature = "async-openai"))]
+mod tests {
+    use std::collections::hash_map::DefaultHasher;
+
+    use tiktoken_rs::num_tokens_from_messages;
+
+    use super::*;
+    use crate::convert::parse_role;
+    use crate::tokenizer::plausible_context_size;
+    use crate::tokenizer::DEFAULT_CONTEXT_SIZE;
+
+    pub(crate) fn user_message(
+        content: impl Into<String>,
+    ) -> async_openai::types::ChatCompletionRequestMessage {
+        async_openai::types::ChatCompletionRequestUserMessageArgs::default()
+            .content(content.into())
+            .build()
+            .unwrap()
+            .into()
+    }
+
+    pub(crate) fn system_message(
+        content: impl Into<String>,
+    ) -> async_openai::types::ChatCompletionRequestMessage {
+        async_openai::types::ChatCompletionRequestSystemMessageArgs::default()
+            .content(content.into())
+            .build()
+            .unwrap()
+            .into()
+    }
+
+    pub(crate) fn assistant_message(
+        content: impl Into<String>,
+    ) -> async_openai::types::ChatCompletionRequestMessage {
+        async_openai::types::ChatCompletionRequestAssistantMessageArgs::default()
+            .content(content.into())
+            .build()
+            .unwrap()
+            .into()
+    }
+
+    pub(crate) fn tool_message(
+        content: impl Into<String>,
+        tool_call_id: impl Into<String>,
+    ) -> async_openai::types::ChatCompletionRequestMessage {
+        async_openai::types::ChatCompletionRequestToolMessageArgs::default()
+            .content(content.into())
+            .tool_call_id(tool_call_id.into())
+            .build()
+            .unwrap()
+            .into()
+    }
+
+    #[derive(Debug)]
+    pub(crate) struct TenTokensPerMessage;
+
+    impl Tokenizer for TenTokensPerMessage {
+        fn count_tokens(
+            &self,
+            messages: &[tiktoken_rs::ChatCompletionRequestMessage],
+        ) -> Result<usize, SplitError> {
+            Ok(10 * messages.len())
+        }
+
+        fn context_size(&self) -> usize {
+            100
+        }
+    }
+
+    pub(crate) fn conversation(
+        turns: usize,
+    ) -> Vec<async_openai::types::ChatCompletionRequestMessage> {
+        (0..turns)
+            .flat_map(|i| {
+                [
+                    user_message(format!("Question number {i}?")),
+                    assistant_message(format!("Answer number {i}.")),
+                ]
+            })
+            .collect()
+    }
+
+    /// A turn where the assistant calls tools before answering.
+    pub(crate) fn weather_turn() -> Vec<async_openai::types::ChatCompletionRequestMessage> {
+        let tool_calls = ["call_boston", "call_tokyo"].map(|id| {
+            async_openai::types::ChatCompletionMessageToolCall {
+                id: id.to_owned(),
+                r#type: async_openai::types::ChatCompletionToolType::Function,
+                function: async_openai::types::FunctionCall {
+                    name: "get_current_weather".to_owned(),
+                    arguments: "{}".to_owned(),
+                },
+            }
+        });
+        vec![
+            user_message("What's the weather like in Boston and Tokyo?"),
+            async_openai::types::ChatCompletionRequestAssistantMessageArgs::default()
+                .tool_calls(tool_calls)
+                .build()
+                .unwrap()
+                .into(),
+            tool_message("22 degrees", "call_boston"),
+            tool_message("18 degrees", "call_tokyo"),
+            user_message("Thanks!"),
+        ]
+    }
+
+    /// Splitters and chats covering the settings that move the boundary,
+    /// from pinned messages and tool groups to a real tokenizer.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn fixtures() -> Vec<(
+        ChatSplitter,
+        Vec<async_openai::types::ChatCompletionRequestMessage>,
+    )> {
+        let mut pinned = vec![system_message("You are a helpful assistant.")];
+        pinned.extend(conversation(5));
+        let mut weather = conversation(2);
+        weather.extend(weather_turn());
+
+        let splitter = ChatSplitter::default().with_tokenizer(TenTokensPerMessage);
+        vec![
+            (splitter.clone().max_tokens(50_u16), conversation(4)),
+            (splitter.clone().max_tokens(50_u16), Vec::new()),
+            (
+                splitter.clone().max_tokens(50_u16).pin_system_prompt(true),
+                pinned.clone(),
+            ),
+            (
+                splitter.clone().max_tokens(60_u16).keep_pairs(true),
+                conversation(6),
+            ),
+            (
+                splitter
+                    .clone()
+                    .max_tokens(0_u16)
+                    .max_messages(5_usize)
+                    .keep_first(1_usize),
+                conversation(6),
+            ),
+            (
+                splitter
+                    .max_tokens(0_u16)
+                    .max_messages(3_usize)
+                    .keep_tool_groups(true),
+                weather,
+            ),
+            (
+                ChatSplitter::new("gpt-4")
+                    .max_input_tokens(64_u16)
+                    .pin_system_prompt(true),
+                pinned,
+            ),
+            (
+                ChatSplitter::new("gpt-4").max_messages(4_usize),
+                conversation(8),
+            ),
+        ]
+    }
+
+    #[test]
+    fn it_works() {
+        let messages: Vec<async_openai::types::ChatCompletionRequestMessage> = Vec::new();
+
+        assert_eq!(ChatSplitter::default().split(&messages).0, &[]);
+        assert_eq!(ChatSplitter::default().split(&messages).1, &[]);
+    }
+
+    #[test]
+    fn try_split_reports_unknown_model() {
+        let messages = vec![user_message("Who won the world series in 2020?")];
+
+        assert_eq!(
+            ChatSplitter::new("not-a-model").try_split(&messages),
+            Err(SplitError::UnknownModel("not-a-model".to_owned()))
+        );
+    }
+
+    #[test]
+    fn tool_messages_can_be_split() {
+        let messages = vec![
+            user_message("What's the weather like in Boston?"),
+            tool_message(r#"{"temperature": 22, "unit": "celsius"}"#, "call_abc123"),
+        ];
+
+        let tool_message = messages[1].clone().into_tiktoken_rs();
+        assert_eq!(tool_message.role, "tool");
+        assert_eq!(tool_message.name.as_deref(), Some("call_abc123"));
+
+        let (outdated, recent) = ChatSplitter::default().split(&messages);
+        assert_eq!(outdated.len() + recent.len(), messages.len());
+    }
+
+    #[test]
+    fn adapters_agree_with_split() {
+        for (splitter, messages) in fixtures() {
+            let (head, outdated, recent) = splitter.split_three(&messages);
+            assert_eq!(splitter.split(&messages), (outdated, recent));
+            let owned = (outdated.to_vec(), [head, recent].concat());
+
+            assert_eq!(splitter.split_owned(messages.clone()), owned);
+            assert_eq!(splitter.split_iter(messages.iter().cloned()), owned);
+            assert_eq!(splitter.fits(&messages), outdated.is_empty());
+
+            let (pinned, outdated_range, recent_range) = splitter.split_ranges(&messages);
+            assert_eq!(
+                (
+                    &messages[pinned],
+                    &messages[outdated_range],
+                    &messages[recent_range]
+                ),
+                (head, outdated, recent)
+            );
+
+            let (stats_outdated, stats_recent, _) = splitter.split_with_stats(&messages);
+            assert_eq!((stats_outdated, stats_recent), (outdated, recent));
+
+            assert_eq!(*splitter.recent_cow(&messages), owned.1);
+
+            let reversed: Vec<_> = messages.iter().rev().cloned().collect();
+            let (reversed_recent, reversed_outdated) = splitter.split_reversed(&reversed);
+            assert!(reversed_recent.iter().rev().eq(recent));
+            assert!(reversed_outdated.iter().rev().eq(outdated));
+
+            let mut pruned = messages.clone();
+            assert_eq!(splitter.prune_in_place(&mut pruned), outdated);
+            assert_eq!(pruned, owned.1);
+        }
+    }
+
+    #[test]
+    fn split_position_matches_split() {
+        let messages: Vec<_> = (0..8)
+            .map(|i| user_message(format!("Message number {i}")))
+            .collect();
+
+        let splitter = ChatSplitter::default();
+        assert_eq!(splitter.split_position(&messages), 0);
+
+        let splitter = splitter.max_messages(3_usize);
+        let n = splitter.split_position(&messages);
+        assert_eq!(n, 5);
+
+        let (outdated, recent) = splitter.split(&messages);
+        assert_eq!(outdated, &messages[..n]);
+        assert_eq!(recent, &messages[n..]);
+    }
+
+    #[test]
+    fn pinned_system_prompt_is_always_recent() {
+        let mut messages = vec![system_message("You are a helpful assistant.")];
+        messages.extend((0..64).map(|i| user_message(format!("Message number {i}. ").repeat(32))));
+
+        for splitter in [
+            ChatSplitter::default().max_messages(8_usize),
+            ChatSplitter::new("gpt-4").max_tokens(7_000_u16),
+        ] {
+            let splitter = splitter.pin_system_prompt(true);
+
+            let (outdated, recent) = splitter.split(&messages);
+            assert!(!outdated.is_empty());
+            assert!(!outdated.contains(&messages[0]));
+            assert!(!recent.contains(&messages[0]));
+
+            let (head, _, _) = splitter.split_three(&messages);
+            assert_eq!(head, &messages[..1]);
+
+            let (outdated, recent) = splitter.split_owned(messages.clone());
+            assert!(!outdated.contains(&messages[0]));
+            assert_eq!(recent[0], messages[0]);
+            assert_eq!(outdated.len() + recent.len(), messages.len());
+        }
+    }
+
+    #[test]
+    fn split_summary_counts_tokens() {
+        let messages: Vec<_> = (0..64)
+            .map(|i| user_message(format!("Message number {i}. ").repeat(32)))
+            .collect();
+
+        let splitter = ChatSplitter::new("gpt-4").max_tokens(7_000_u16);
+        let summary = splitter.split_summary(&messages);
+        assert_eq!(summary.position, splitter.split_position(&messages));
+        assert!(summary.outdated_tokens > 0);
+        assert!(summary.recent_tokens > 0);
+        assert!(summary.recent_tokens <= 8_192 - 7_000);
+    }
+
+    #[test]
+    fn custom_tokenizer_is_used() {
+        let messages: Vec<_> = (0..8)
+            .map(|i| user_message(format!("Message number {i}")))
+            .collect();
+
+        let splitter = ChatSplitter::new("not-a-model")
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(50_u16);
+        assert_eq!(splitter.split_position(&messages), 3);
+    }
+
+    #[test]
+    fn keep_pairs_never_starts_with_assistant() {
+        let messages = conversation(5);
+
+        let splitter = ChatSplitter::default().max_messages(3_usize);
+        assert_eq!(splitter.split_position(&messages), 7);
+
+        let splitter = splitter.keep_pairs(true);
+        let (outdated, recent) = splitter.split(&messages);
+        assert_eq!(outdated.len(), 8);
+        assert_eq!(role(&recent[0]).unwrap(), "user");
+
+        let splitter = splitter.max_messages(4_usize);
+        assert_eq!(splitter.split_position(&messages), 6);
+    }
+
+    #[test]
+    fn message_limit_overrides_max_messages() {
+        let messages = conversation(4);
+
+        let splitter = ChatSplitter::default().message_limit(3_usize);
+        assert_eq!(splitter.split_position(&messages), 5);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let splitter = ChatSplitter::new("gpt-4")
+            .max_tokens(1_024_u16)
+            .max_messages(16_usize)
+            .keep_pairs(true);
+
+        let json = serde_json::to_string(&splitter).unwrap();
+        let deserialized: ChatSplitter = serde_json::from_str(&json).unwrap();
+        assert_eq!(format!("{deserialized:?}"), format!("{splitter:?}"));
+    }
+
+    #[test]
+    fn windows_shrink_down_to_empty() {
+        let messages = conversation(3);
+
+        let splitter = ChatSplitter::default().max_messages(5_usize);
+        let windows: Vec<_> = splitter
+            .windows(&messages)
+            .map(|window| window.len())
+            .collect();
+        assert_eq!(windows, [5, 4, 3, 2, 1, 0]);
+
+        let splitter = splitter.keep_pairs(true);
+        let windows: Vec<_> = splitter
+            .windows(&messages)
+            .map(|window| window.len())
+            .collect();
+        assert_eq!(windows, [4, 2, 0]);
+    }
+
+    #[test]
+    fn max_input_tokens_is_a_ceiling() {
+        let messages = conversation(4);
+
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(0_u16);
+        assert_eq!(
+            splitter
+                .clone()
+                .max_input_tokens(50_u16)
+                .split_position(&messages),
+            3
+        );
+        assert_eq!(
+            splitter
+                .clone()
+                .max_input_tokens(49_u16)
+                .split_position(&messages),
+            4
+        );
+
+        // The tighter limit wins.
+        let splitter = splitter.max_tokens(70_u16).max_input_tokens(50_u16);
+        assert_eq!(splitter.split_position(&messages), 5);
+    }
+
+    #[test]
+    fn tool_calls_are_counted() {
+        let tool_calls = vec![async_openai::types::ChatCompletionMessageToolCall {
+            id: "call_abc123".to_owned(),
+            r#type: async_openai::types::ChatCompletionToolType::Function,
+            function: async_openai::types::FunctionCall {
+                name: "get_current_weather".to_owned(),
+                arguments: r#"{"location": "Boston, MA", "unit": "celsius"}"#.to_owned(),
+            },
+        }];
+        let without_tool_calls: async_openai::types::ChatCompletionRequestMessage =
+            async_openai::types::ChatCompletionRequestAssistantMessageArgs::default()
+                .build()
+                .unwrap()
+                .into();
+        let with_tool_calls: async_openai::types::ChatCompletionRequestMessage =
+            async_openai::types::ChatCompletionRequestAssistantMessageArgs::default()
+                .tool_calls(tool_calls)
+                .build()
+                .unwrap()
+                .into();
+
+        let tokens = |message: async_openai::types::ChatCompletionRequestMessage| {
+            num_tokens_from_messages("gpt-3.5-turbo", &[message.into_tiktoken_rs()]).unwrap()
+        };
+        assert!(tokens(with_tool_calls) > tokens(without_tool_calls) + 10);
+    }
+
+    #[test]
+    fn min_messages_overrides_max_tokens() {
+        let mut messages = conversation(2);
+        messages.push(user_message("Lorem ipsum dolor sit amet. ".repeat(2_000)));
+
+        let splitter = ChatSplitter::new("gpt-4");
+        assert!(splitter.split(&messages).1.is_empty());
+
+        let splitter = splitter.min_messages(1_usize);
+        assert_eq!(splitter.split(&messages).1, &messages[4..]);
+
+        let splitter = splitter.max_messages(0_usize);
+        assert!(splitter.split(&messages).1.is_empty());
+    }
+
+    #[test]
+    fn context_size_uses_known_models() {
+        assert_eq!(ChatSplitter::new("gpt-4").context_size(), 8_192);
+        assert_eq!(plausible_context_size(8_192), 8_192);
+        assert_eq!(plausible_context_size(0), DEFAULT_CONTEXT_SIZE);
+        assert_eq!(ChatSplitter::new("gpt-4-turbo").context_size(), 128_000);
+        assert_eq!(ChatSplitter::new("gpt-4o-mini").context_size(), 128_000);
+        assert_eq!(
+            ChatSplitter::default()
+                .with_tokenizer(TenTokensPerMessage)
+                .context_size(),
+            100
+        );
+    }
+
+    #[test]
+    fn split_with_visits_outdated_in_order() {
+        let messages = conversation(4);
+
+        let splitter = ChatSplitter::default().max_messages(5_usize);
+        let mut visited = Vec::new();
+        let (outdated, recent) =
+            splitter.split_with(&messages, |message| visited.push(message.clone()));
+
+        assert_eq!(visited, outdated);
+        assert_eq!((outdated, recent), splitter.split(&messages));
+    }
+
+    #[test]
+    fn count_tokens_matches_tiktoken_rs() {
+        let messages = conversation(3);
+
+        let tokens = ChatSplitter::default().count_tokens(&messages).unwrap();
+        assert_eq!(
+            tokens,
+            num_tokens_from_messages("gpt-3.5-turbo", &into_tiktoken_rs_vec(messages.clone()))
+                .unwrap()
+        );
+        assert_eq!(
+            ChatSplitter::new("not-a-model").count_tokens(&messages),
+            Err(SplitError::UnknownModel("not-a-model".to_owned()))
+        );
+    }
+
+    #[test]
+    fn message_tokens_add_up() {
+        let mut messages = conversation(3);
+        messages.push(
+            async_openai::types::ChatCompletionRequestUserMessageArgs::default()
+                .content("Hello!")
+                .name("felipe")
+                .build()
+                .unwrap()
+                .into(),
+        );
+        let messages = into_tiktoken_rs_vec(messages);
+
+        for model in ["gpt-3.5-turbo", "gpt-4"] {
+            let tokenizer = TiktokenTokenizer::new(model);
+            let message_tokens: usize = tokenizer
+                .count_message_tokens(&messages)
+                .unwrap()
+                .iter()
+                .sum();
+            assert_eq!(
+                tokenizer.count_tokens(&[]).unwrap() + message_tokens,
+                tokenizer.count_tokens(&messages).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn split_with_max_tokens_overrides_once() {
+        let messages = conversation(4);
+
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(60_u16);
+        assert_eq!(splitter.split_with_max_tokens(&messages, 80_u16).1.len(), 2);
+        assert_eq!(splitter.split(&messages).1.len(), 4);
+    }
+
+    #[test]
+    fn keep_tool_groups_skips_orphaned_tool_messages() {
+        let messages = weather_turn();
+
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(0_u16)
+            .max_messages(3_usize);
+        assert_eq!(splitter.split_position(&messages), 2);
+
+        let splitter = splitter.keep_tool_groups(true);
+        assert_eq!(splitter.split_position(&messages), 4);
+    }
+
+    #[test]
+    fn estimator_is_used() {
+        let messages = conversation(4);
+
+        let splitter = ChatSplitter::new("not-a-model")
+            .with_estimator(|_| 10)
+            .max_tokens(4_046_u16);
+        assert_eq!(splitter.context_size(), 4_096);
+        assert_eq!(splitter.split_position(&messages), 3);
+
+        let splitter = ChatSplitter::default().with_estimator(estimate_tokens);
+        assert_eq!(splitter.split_position(&messages), 0);
+
+        // The context size follows the model.
+        let splitter = splitter.model("gpt-4-32k");
+        assert_eq!(splitter.context_size(), 32_768);
+        let splitter = splitter.with_tokenizer(TenTokensPerMessage);
+        assert_eq!(splitter.context_size(), 100);
+    }
+
+    #[test]
+    fn max_tokens_must_leave_room_for_input() {
+        let messages = conversation(2);
+
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(100_u16);
+        assert_eq!(
+            splitter.try_split(&messages),
+            Err(SplitError::CompletionBudgetTooLarge {
+                max_tokens: 100,
+                context_size: 100,
+            })
+        );
+
+        let splitter = splitter.max_tokens(90_u16);
+        assert_eq!(splitter.try_split_position(&messages), Ok(3));
+    }
+
+    #[test]
+    fn reserve_completion_tokens_sets_max_tokens() {
+        let messages = conversation(4);
+
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .reserve_completion_tokens(30_u32);
+        assert_eq!(splitter.max_tokens, 30);
+        assert_eq!(
+            splitter.split(&messages),
+            splitter.clone().max_tokens(30_u16).split(&messages)
+        );
+        assert_eq!(splitter.split_position(&messages), 1);
+
+        // This is what the warning is about.
+        let splitter = splitter.reserve_completion_tokens(100_u32);
+        assert_eq!(splitter.max_tokens, 100);
+        assert!(splitter.max_tokens as usize >= splitter.context_size());
+        assert_eq!(
+            splitter.try_split(&messages),
+            Err(SplitError::CompletionBudgetTooLarge {
+                max_tokens: 100,
+                context_size: 100,
+            })
+        );
+    }
+
+    #[test]
+    fn messages_only_never_tokenizes() {
+        let messages = conversation(4);
+
+        let splitter = ChatSplitter::new("not-a-model")
+            .strategy(SplitStrategy::MessagesOnly)
+            .max_messages(3_usize);
+        assert_eq!(splitter.try_split_position(&messages), Ok(5));
+
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(70_u16)
+            .max_messages(1_usize);
+        assert_eq!(splitter.split_position(&messages), 7);
+        let splitter = splitter.strategy(SplitStrategy::TokensOnly);
+        assert_eq!(splitter.split_position(&messages), 5);
+    }
+
+    #[test]
+    fn images_are_estimated() {
+        let message: async_openai::types::ChatCompletionRequestMessage =
+            async_openai::types::ChatCompletionRequestUserMessageArgs::default()
+                .content(vec![
+                    async_openai::types::ChatCompletionRequestMessageContentPartTextArgs::default()
+                        .text("What is in this image?")
+                        .build()
+                        .unwrap()
+                        .into(),
+                    async_openai::types::ChatCompletionRequestMessageContentPartImageArgs::default(
+                    )
+                    .image_url("https://example.com/image.png")
+                    .build()
+                    .unwrap()
+                    .into(),
+                ])
+                .build()
+                .unwrap()
+                .into();
+        let messages = [message];
+
+        assert_eq!(messages[0].image_count(), 1);
+        assert_eq!(
+            messages[0].clone().into_tiktoken_rs().content.as_deref(),
+            Some("What is in this image?")
+        );
+
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(0_u16)
+            .image_tokens(85_usize);
+        assert_eq!(splitter.count_tokens(&messages), Ok(95));
+        assert_eq!(splitter.split_position(&messages), 0);
+        assert_eq!(splitter.image_tokens(95_usize).split_position(&messages), 1);
+    }
+
+    #[test]
+    fn strict_mode_rejects_images() {
+        let image: async_openai::types::ChatCompletionRequestMessage =
+            async_openai::types::ChatCompletionRequestUserMessageArgs::default()
+                .content(vec![
+                    async_openai::types::ChatCompletionRequestMessageContentPartTextArgs::default()
+                        .text("What is in this image?")
+                        .build()
+                        .unwrap()
+                        .into(),
+                    async_openai::types::ChatCompletionRequestMessageContentPartImageArgs::default(
+                    )
+                    .image_url("https://example.com/image.png")
+                    .build()
+                    .unwrap()
+                    .into(),
+                ])
+                .build()
+                .unwrap()
+                .into();
+        let mut messages = conversation(2);
+        messages.push(image);
+
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(50_u16)
+            .image_tokens(5_usize);
+        assert_eq!(splitter.count_tokens(&messages), Ok(55));
+        assert_eq!(splitter.split(&messages).1, &messages[1..]);
+
+        let splitter = splitter.strict(true);
+        let error = SplitError::LossyConversion { index: 4 };
+        assert_eq!(splitter.count_tokens(&messages), Err(error.clone()));
+        assert_eq!(splitter.try_split(&messages), Err(error));
+        assert_eq!(
+            splitter.try_split(&messages[..4]),
+            Ok((&messages[..0], &messages[..4]))
+        );
+    }
+
+    #[test]
+    fn split_with_stats_agrees_with_split() {
+        let messages = conversation(4);
+
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(50_u16);
+        let (outdated, recent, stats) = splitter.split_with_stats(&messages);
+        assert_eq!((outdated, recent), splitter.split(&messages));
+        assert_eq!(
+            stats,
+            SplitStats {
+                outdated_messages: 3,
+                recent_messages: 5,
+                outdated_tokens: 30,
+                recent_tokens: 50,
+                binding_constraint: BindingConstraint::Tokens,
+            }
+        );
+    }
+
+    #[test]
+    fn middle_trim_keeps_head() {
+        let messages = conversation(4);
+
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(50_u16)
+            .trim_mode(TrimMode::Middle)
+            .head_messages(2_usize);
+        let (head, outdated, recent) = splitter.split_three(&messages);
+        assert_eq!(head, &messages[..2]);
+        assert_eq!(outdated, &messages[2..5]);
+        assert_eq!(recent, &messages[5..]);
+        assert_eq!(splitter.split_summary(&messages).recent_tokens, 50);
+
+        let (outdated, recent) = splitter.split_owned(messages.clone());
+        assert_eq!(outdated, &messages[2..5]);
+        assert_eq!(recent[..2], messages[..2]);
+        assert_eq!(recent[2..], messages[5..]);
+    }
+
+    #[test]
+    fn fast_path_agrees_with_tokenizing() {
+        let messages = conversation(2);
+        let bytes: usize = messages
+            .iter()
+            .map(|message| {
+                let message = message.clone().into_tiktoken_rs();
+                4 + message.role.len() + message.content.map_or(0, |content| content.len())
+            })
+            .sum::<usize>()
+            + 3;
+
+        for max_input_tokens in [bytes - 1, bytes] {
+            let splitter = ChatSplitter::default().max_input_tokens(max_input_tokens as u16);
+            let tokenizing = splitter
+                .clone()
+                .with_tokenizer(TiktokenTokenizer::new("gpt-3.5-turbo"));
+            assert_eq!(
+                splitter
+                    .fits_without_tokenizing(&try_into_tiktoken_rs(&messages).unwrap(), &messages),
+                Ok(max_input_tokens == bytes)
+            );
+            assert_eq!(
+                splitter.split_position(&messages),
+                tokenizing.split_position(&messages)
+            );
+        }
+    }
+
+    #[test]
+    fn max_tokens_can_exceed_u16() {
+        let messages = conversation(2);
+
+        let splitter = ChatSplitter::new("gpt-4o").max_tokens(100_000_u32);
+        assert_eq!(splitter.split_position(&messages), 0);
+        assert_eq!(
+            splitter
+                .max_tokens(128_000_u32)
+                .try_split_position(&messages),
+            Err(SplitError::CompletionBudgetTooLarge {
+                max_tokens: 128_000,
+                context_size: 128_000,
+            })
+        );
+    }
+
+    #[test]
+    fn validate_messages_reports_index() {
+        /// Message that rejects roles not known to the API.
+        #[derive(Clone)]
+        struct Strict(tiktoken_rs::ChatCompletionRequestMessage);
+
+        impl IntoChatCompletionRequestMessage for Strict {
+            fn into_tiktoken_rs(self) -> tiktoken_rs::ChatCompletionRequestMessage {
+                self.try_into_tiktoken_rs()
+                    .unwrap_or_else(|error| panic!("{error}"))
+            }
+
+            fn into_async_openai(self) -> async_openai::types::ChatCompletionRequestMessage {
+                self.0.into_async_openai()
+            }
+
+            fn try_into_tiktoken_rs(
+                self,
+            ) -> Result<tiktoken_rs::ChatCompletionRequestMessage, ConversionError> {
+                match self.0.role.as_ref() {
+                    "system" | "user" | "assistant" | "tool" | "function" => Ok(self.0),
+                    role => Err(ConversionError::UnsupportedRole(role.to_owned())),
+                }
+            }
+        }
+
+        let mut messages: Vec<_> = conversation(2)
+            .into_iter()
+            .map(|message| Strict(message.into_tiktoken_rs()))
+            .collect();
+        let splitter = ChatSplitter::default();
+        assert_eq!(splitter.validate_messages(&messages), Ok(()));
+
+        messages[2].0.role = "developer".to_owned();
+        messages.push(messages[2].clone());
+        let error = splitter.validate_messages(&messages).unwrap_err();
+        assert_eq!(
+            error,
+            ConversionError::InvalidMessage {
+                index: 2,
+                error: Box::new(ConversionError::UnsupportedRole("developer".to_owned())),
+            }
+        );
+        assert_eq!(
+            SplitError::from(error),
+            SplitError::UnsupportedRole("developer".to_owned())
+        );
+
+        // Roles are also parsed as in the conversion to `async_openai`.
+        let messages = [
+            SimpleMessage::new("user", "Hi!"),
+            SimpleMessage::new("moderator", "Be nice."),
+        ];
+        assert_eq!(
+            splitter.validate_messages(&messages),
+            Err(ConversionError::InvalidMessage {
+                index: 1,
+                error: Box::new(ConversionError::UnsupportedRole("moderator".to_owned())),
+            })
+        );
+    }
+
+    #[test]
+    fn groups_are_never_cut() {
+        let groups: Vec<_> = conversation(4).chunks(3).map(<[_]>::to_vec).collect();
+
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(0_u16);
+        for max_input_tokens in 0..=80_u16 {
+            let splitter = splitter.clone().max_input_tokens(max_input_tokens);
+            let (outdated, recent) = splitter.split_groups(&groups);
+            assert_eq!(outdated.len() + recent.len(), groups.len());
+
+            let recent = recent.iter().map(Vec::len).sum::<usize>();
+            let expected = [8, 5, 2, 0]
+                .into_iter()
+                .find(|&n| 10 * n <= max_input_tokens as usize)
+                .unwrap();
+            assert_eq!(recent, expected);
+        }
+
+        // Groups with pinned messages are never 'outdated'.
+        let mut groups = groups;
+        groups[0].insert(0, system_message("You are a helpful assistant."));
+        let splitter = splitter.pin_system_prompt(true).max_input_tokens(20_u16);
+        let (outdated, recent) = splitter.split_groups(&groups);
+        assert_eq!(outdated, &groups[1..]);
+        assert!(recent.is_empty());
+
+        let splitter = splitter.max_input_tokens(30_u16);
+        let (outdated, recent) = splitter.split_groups(&groups);
+        assert_eq!(outdated, &groups[1..2]);
+        assert_eq!(recent, &groups[2..]);
+    }
+
+    #[test]
+    fn token_overhead_is_used() {
+        let messages = conversation(2);
+
+        let splitter = ChatSplitter::default();
+        let tokens = splitter.count_tokens(&messages).unwrap();
+        let splitter = splitter.token_overhead(6, 5);
+        assert_eq!(
+            splitter.count_tokens(&messages),
+            Ok(tokens + 2 * messages.len() + 2)
+        );
+    }
+
+    #[test]
+    fn split_by_budget_ignores_max_tokens() {
+        let messages = conversation(4);
+
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(90_u16);
+        assert_eq!(splitter.split_by_budget(&messages, 50).1.len(), 5);
+        assert_eq!(splitter.split_by_budget(&messages, 1_000).1.len(), 8);
+        assert_eq!(splitter.split(&messages).1.len(), 1);
+
+        let splitter = splitter.max_messages(3_usize);
+        assert_eq!(splitter.split_by_budget(&messages, 50).1.len(), 3);
+    }
+
+    #[test]
+    fn tokenizer_model_is_used_for_counting() {
+        let messages = conversation(2);
+
+        let splitter = ChatSplitter::new("my-gpt-4-alias");
+        assert_eq!(
+            splitter.count_tokens(&messages),
+            Err(SplitError::UnknownModel("my-gpt-4-alias".to_owned()))
+        );
+
+        let splitter = splitter.tokenizer_model("gpt-4");
+        assert_eq!(
+            splitter.count_tokens(&messages),
+            ChatSplitter::new("gpt-4").count_tokens(&messages)
+        );
+        assert_eq!(splitter.context_size(), 4_096);
+    }
+
+    #[test]
+    fn oversized_message_is_truncated() {
+        let mut messages = conversation(1);
+        messages.push(user_message("hello ".repeat(133_334)));
+
+        let splitter = ChatSplitter::default().with_estimator(estimate_tokens);
+        assert_eq!(splitter.count_tokens(&messages[2..]), Ok(200_006));
+        let (outdated, recent) = splitter.split_owned(messages.clone());
+        assert_eq!((outdated.len(), recent.len()), (3, 0));
+
+        let splitter = splitter.truncate_oversized(true);
+        let (outdated, recent) = splitter.split_owned(messages);
+        assert_eq!((outdated.len(), recent.len()), (2, 1));
+        assert_eq!(
+            splitter.count_tokens(&recent),
+            Ok(splitter.context_size() - splitter.max_tokens as usize)
+        );
+    }
+
+    #[test]
+    fn oversized_message_is_truncated_at_token_boundary() {
+        let mut messages = conversation(1);
+        messages.push(user_message("héllo ".repeat(20_000)));
+
+        let splitter = ChatSplitter::new("gpt-4").truncate_oversized(true);
+        let (outdated, recent) = splitter.split_owned(messages.clone());
+        assert_eq!((outdated.len(), recent.len()), (2, 1));
+        assert_eq!(
+            splitter.count_tokens(&recent),
+            Ok(splitter.context_size() - splitter.max_tokens as usize)
+        );
+        assert!(recent[0]
+            .clone()
+            .into_tiktoken_rs()
+            .content
+            .is_some_and(|content| content.starts_with("héllo héllo")));
+    }
+
+    #[test]
+    fn utilization_is_clamped() {
+        let messages = conversation(4);
+
+        let splitter = ChatSplitter::default().with_tokenizer(TenTokensPerMessage);
+        assert!((splitter.utilization(&messages[..4]) - 0.4).abs() < f64::EPSILON);
+        assert!((splitter.utilization(&conversation(10)) - 1.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn snap_to_turns_starts_with_user() {
+        let messages = vec![
+            user_message("What's the weather?"),
+            assistant_message("Let me check."),
+            tool_message("Sunny", "call_0"),
+            assistant_message("It's sunny."),
+            user_message("And tomorrow?"),
+            assistant_message("Let me check."),
+            tool_message("Rainy", "call_1"),
+            assistant_message("It'll rain."),
+        ];
+
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(0_u16)
+            .snap_to_turns(true);
+        for max_input_tokens in 0..=80_u16 {
+            let splitter = splitter.clone().max_input_tokens(max_input_tokens);
+            let n = 8 - usize::from(max_input_tokens / 10);
+            let expected = match n {
+                0 => 0,
+                1..=4 => 4,
+                _ => n,
+            };
+            assert_eq!(splitter.split_position(&messages), expected);
+        }
+    }
+
+    #[test]
+    fn trait_objects_can_be_split() {
+        let messages: Vec<Box<dyn ToChatCompletionRequestMessage>> = vec![
+            Box::new(user_message("Hello")),
+            Box::new(assistant_message("Hi!").into_tiktoken_rs()),
+            Box::new(user_message("How are you?")),
+        ];
+        let messages: Vec<&dyn ToChatCompletionRequestMessage> =
+            messages.iter().map(AsRef::as_ref).collect();
+
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(80_u16);
+        let (outdated, recent) = splitter.split(&messages);
+        assert_eq!((outdated.len(), recent.len()), (1, 2));
+        assert_eq!(
+            into_async_openai_vec(recent.iter().copied()),
+            [assistant_message("Hi!"), user_message("How are you?")]
+        );
+    }
+
+    #[test]
+    fn split_appending_keeps_new_messages() {
+        let history = conversation(4);
+        let new = [user_message("And now?")];
+
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(70_u16);
+        let (outdated, recent) = splitter.split_appending(&history, &new);
+        assert_eq!(outdated, &history[..6]);
+        assert_eq!(recent, [&history[6..], &new].concat());
+    }
+
+    #[test]
+    fn split_at_exact_token_boundary() {
+        let messages = conversation(4);
+
+        // 30 input tokens left, exactly three messages
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(70_u16);
+        let (outdated, recent) = splitter.split(&messages);
+        assert_eq!(outdated, &messages[..5]);
+        assert_eq!(recent, &messages[5..]);
+
+        // One token short of the boundary
+        let splitter = splitter.max_tokens(71_u16);
+        let (outdated, recent) = splitter.split(&messages);
+        assert_eq!(outdated, &messages[..6]);
+        assert_eq!(recent, &messages[6..]);
+    }
+
+    #[test]
+    fn pinned_last_message_is_always_recent() {
+        let mut messages = conversation(4);
+        messages.insert(0, system_message("You are a helpful assistant."));
+
+        for max_tokens in [0_u16, 50, 80, 95] {
+            let splitter = ChatSplitter::default()
+                .with_tokenizer(TenTokensPerMessage)
+                .max_tokens(max_tokens)
+                .pin_system_prompt(true)
+                .pin_last(true);
+            let (head, _outdated, recent) = splitter.split_three(&messages);
+            assert_eq!(head, &messages[..1]);
+            assert_eq!(recent.last(), messages.last());
+        }
+
+        // Both pinned messages are kept even if they do not fit together.
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(95_u16)
+            .pin_system_prompt(true)
+            .pin_last(true);
+        let (head, outdated, recent) = splitter.split_three(&messages);
+        assert_eq!(head, &messages[..1]);
+        assert_eq!(outdated, &messages[1..8]);
+        assert_eq!(recent, &messages[8..]);
+    }
+
+    #[test]
+    fn from_request_reads_model_and_max_tokens() {
+        let request = async_openai::types::CreateChatCompletionRequestArgs::default()
+            .model("gpt-4")
+            .messages([user_message("Hello!")])
+            .max_tokens(512_u16)
+            .build()
+            .unwrap();
+        let splitter = ChatSplitter::from_request(&request);
+        assert_eq!(splitter.model, "gpt-4");
+        assert_eq!(splitter.max_tokens, 512);
+
+        let request = async_openai::types::CreateChatCompletionRequestArgs::default()
+            .model("gpt-3.5-turbo")
+            .messages([user_message("Hello!")])
+            .build()
+            .unwrap();
+        let splitter = ChatSplitter::from_request(&request);
+        assert_eq!(splitter.model, "gpt-3.5-turbo");
+        assert_eq!(
+            splitter.max_tokens,
+            ChatSplitter::new("gpt-3.5-turbo").max_tokens
+        );
+    }
+
+    #[test]
+    fn recent_never_exceeds_max_messages() {
+        let mut messages = vec![system_message("You are a helpful assistant.")];
+        messages.extend(conversation(3));
+        messages.push(tool_message("42", "call_0"));
+        messages.push(assistant_message("The answer is 42."));
+
+        for max_messages in 0_usize..=messages.len() {
+            for min_messages in 0_usize..4 {
+                for max_tokens in [0_u16, 50, 90] {
+                    for trim_mode in [TrimMode::Front, TrimMode::Middle] {
+                        for flags in 0_u8..16 {
+                            let flag = |i: u8| flags & (1 << i) != 0;
+                            let splitter = ChatSplitter::default()
+                                .with_tokenizer(TenTokensPerMessage)
+                                .max_messages(max_messages)
+                                .min_messages(min_messages)
+                                .max_tokens(max_tokens)
+                                .trim_mode(trim_mode)
+                                .pin_system_prompt(flag(0))
+                                .pin_last(flag(1))
+                                .keep_pairs(flag(2))
+                                .keep_tool_groups(true)
+                                .snap_to_turns(flag(3));
+                            let (head, _outdated, recent) = splitter.split_three(&messages);
+                            assert!(recent.len() <= max_messages);
+                            if head.len() <= max_messages {
+                                assert!(head.len() + recent.len() <= max_messages);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn estimate_cost_is_proportional_to_tokens() {
+        let messages = conversation(2);
+
+        let splitter = ChatSplitter::default().with_tokenizer(TenTokensPerMessage);
+        assert!((splitter.estimate_cost(&messages, 0.5) - 0.02).abs() < f64::EPSILON);
+        assert!(splitter.estimate_cost(&messages[..0], 0.5).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn best_fit_picks_first_model_that_fits() {
+        let messages: Vec<_> = (0..16)
+            .map(|i| user_message(format!("Message number {i}. ").repeat(128)))
+            .collect();
+        let models = ["gtp-4", "gpt-4", "gpt-4-32k"];
+
+        let splitter = ChatSplitter::default().max_tokens(1_000_u16);
+        assert_eq!(
+            splitter.best_fit(&messages[..4], &models).as_deref(),
+            Some("gpt-4")
+        );
+        assert_eq!(
+            splitter.best_fit(&messages, &models).as_deref(),
+            Some("gpt-4-32k")
+        );
+
+        // Limits are the same as when splitting.
+        let tokens = splitter
+            .clone()
+            .model("gpt-4")
+            .count_tokens(&messages[..4])
+            .unwrap();
+        assert_eq!(
+            splitter
+                .clone()
+                .reserve_prefix(&messages[4..])
+                .best_fit(&messages[..4], &models)
+                .as_deref(),
+            Some("gpt-4-32k")
+        );
+        assert_eq!(
+            splitter
+                .clone()
+                .combined_ceiling(1_000 + tokens - 1)
+                .best_fit(&messages[..4], &models),
+            None
+        );
+        assert_eq!(
+            splitter
+                .clone()
+                .combined_ceiling(1_000 + tokens)
+                .best_fit(&messages[..4], &models)
+                .as_deref(),
+            Some("gpt-4")
+        );
+        assert_eq!(
+            splitter
+                .clone()
+                .max_tokens(0_u16)
+                .with_tokenizer(TenTokensPerMessage)
+                .best_fit(&messages[..4], &models)
+                .as_deref(),
+            Some("gtp-4")
+        );
+
+        let splitter = splitter.max_tokens(32_000_u16);
+        assert_eq!(splitter.best_fit(&messages, &models), None);
+    }
+
+    #[test]
+    fn split_ranges_partition_the_chat() {
+        let messages = conversation(4);
+
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(70_u16);
+        let (pinned, outdated, recent) = splitter.split_ranges(&messages);
+        assert_eq!(pinned, 0..0);
+        assert_eq!(outdated, 0..5);
+        assert_eq!(recent, 5..8);
+        assert_eq!(
+            (&messages[outdated], &messages[recent]),
+            splitter.split(&messages)
+        );
+
+        let splitter = splitter.keep_first(1_usize);
+        let (pinned, outdated, recent) = splitter.split_ranges(&messages);
+        assert_eq!(pinned, 0..1);
+        assert_eq!(outdated, 1..6);
+        assert_eq!(recent, 6..8);
+        assert_eq!(
+            (&messages[outdated.clone()], &messages[recent.clone()]),
+            splitter.split(&messages)
+        );
+        assert_eq!(
+            (
+                messages[outdated].to_vec(),
+                [&messages[pinned], &messages[recent]].concat()
+            ),
+            splitter.split_owned(messages.clone())
+        );
+    }
+
+    #[test]
+    fn plan_reports_what_moved_the_boundary() {
+        let messages = conversation(4);
+
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(70_u16);
+        assert_eq!(
+            splitter.plan(&messages),
+            SplitPlan {
+                position: 5,
+                pinned_messages: 0,
+                outdated_messages: 5,
+                recent_messages: 3,
+                max_messages_hit: false,
+                max_tokens_hit: true,
+                kept_pairs: false,
+                snapped_to_turn: false,
+                min_messages_hit: false,
+            }
+        );
+
+        let plan = splitter.clone().keep_pairs(true).plan(&messages);
+        assert_eq!(plan.position, 6);
+        assert!(plan.kept_pairs);
+
+        let plan = splitter.clone().max_messages(2_usize).plan(&messages);
+        assert_eq!(plan.position, 6);
+        assert!(plan.max_messages_hit);
+        assert!(!plan.max_tokens_hit);
+
+        let plan = splitter
+            .clone()
+            .max_tokens(90_u16)
+            .min_messages(2_usize)
+            .plan(&messages);
+        assert_eq!(plan.position, 6);
+        assert!(plan.min_messages_hit);
+
+        let (outdated, recent) = messages.split_at(plan.position);
+        assert_eq!((outdated.len(), recent.len()), (6, 2));
+
+        // Plans apply exactly as splits do,
+        // pinned messages and all.
+        let splitter = splitter.keep_first(1_usize);
+        let plan = splitter.plan(&messages);
+        assert_eq!(plan.outdated(), 1..6);
+        assert_eq!(plan.split(&messages), splitter.split(&messages));
+    }
+
+    #[test]
+    fn long_names_are_counted() {
+        let named_message = |name: &str| -> async_openai::types::ChatCompletionRequestMessage {
+            async_openai::types::ChatCompletionRequestUserMessageArgs::default()
+                .content("Hello!")
+                .name(name)
+                .build()
+                .unwrap()
+                .into()
+        };
+        let short = [named_message("felipe"), named_message("felipe")];
+        let long = [
+            named_message(&"very_long_name_".repeat(64)),
+            named_message("felipe"),
+        ];
+        assert_eq!(
+            short[0].clone().into_tiktoken_rs().name.as_deref(),
+            Some("felipe")
+        );
+
+        let splitter = ChatSplitter::new("gpt-4");
+        let tokens = splitter.count_tokens(&short).unwrap();
+        assert!(splitter.count_tokens(&long).unwrap() > tokens + 64);
+
+        let splitter = splitter.max_input_tokens(u32::try_from(tokens).unwrap());
+        assert_eq!(splitter.split_position(&short), 0);
+        assert_eq!(splitter.split_position(&long), 1);
+    }
+
+    #[test]
+    fn builder_validates_settings() {
+        assert!(ChatSplitter::builder().build().is_ok());
+        assert!(ChatSplitter::builder()
+            .model("gpt-4")
+            .max_tokens(1_000_u16)
+            .max_messages(8_usize)
+            .min_messages(2_usize)
+            .build()
+            .is_ok());
+
+        assert_eq!(
+            ChatSplitter::builder().model("gtp-4").build().unwrap_err(),
+            SplitError::UnknownModel("gtp-4".to_owned())
+        );
+        assert!(ChatSplitter::builder()
+            .model("gtp-4")
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(0_u16)
+            .build()
+            .is_ok());
+
+        assert_eq!(
+            ChatSplitter::builder()
+                .model("gpt-4")
+                .max_tokens(8_192_u16)
+                .build()
+                .unwrap_err(),
+            SplitError::CompletionBudgetTooLarge {
+                max_tokens: 8_192,
+                context_size: 8_192,
+            }
+        );
+
+        assert_eq!(
+            ChatSplitter::builder()
+                .max_messages(64_usize)
+                .message_limit(32_usize)
+                .build()
+                .unwrap_err(),
+            SplitError::MaxMessagesTooLarge {
+                max_messages: 64,
+                message_limit: 32,
+            }
+        );
+
+        assert_eq!(
+            ChatSplitter::builder()
+                .max_messages(4_usize)
+                .min_messages(8_usize)
+                .build()
+                .unwrap_err(),
+            SplitError::MinMessagesTooLarge {
+                min_messages: 8,
+                max_messages: 4,
+            }
+        );
+
+        let splitter = ChatSplitterBuilder::from(ChatSplitter::default().keep_pairs(true)).build();
+        assert!(splitter.unwrap().keep_pairs);
+    }
+
+    #[test]
+    fn reserved_tokens_shrink_recent() {
+        let messages = conversation(4);
+
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(50_u16);
+        assert_eq!(splitter.split_position(&messages), 3);
+
+        let splitter = splitter.reserve_tokens(20_usize);
+        assert_eq!(splitter.split_position(&messages), 5);
+        assert_eq!(splitter.split_by_budget(&messages, 50).1, &messages[3..]);
+    }
+
+    #[test]
+    fn split_counted_uses_reported_tokens() {
+        /// Message with a token count from elsewhere.
+        #[derive(Clone, Debug, PartialEq)]
+        struct Cached(async_openai::types::ChatCompletionRequestMessage, usize);
+
+        impl IntoChatCompletionRequestMessage for Cached {
+            fn into_tiktoken_rs(self) -> tiktoken_rs::ChatCompletionRequestMessage {
+                self.0.into_tiktoken_rs()
+            }
+        }
+
+        impl TokenCounted for Cached {
+            fn token_count(&self, _model: &str) -> usize {
+                self.1
+            }
+        }
+
+        let messages: Vec<_> = [40, 10, 20, 30]
+            .into_iter()
+            .map(|tokens| Cached(user_message("Hi!"), tokens))
+            .collect();
+        let splitter = ChatSplitter::new("gpt-4")
+            .max_tokens(8_192_u16 - 53)
+            .token_overhead(3_usize, 3_usize);
+        let (outdated, recent) = splitter.split_counted(&messages);
+        assert_eq!(outdated.len(), 2);
+        assert_eq!(recent.len(), 2);
+
+        // Pinned messages are kept as in any other split.
+        let splitter = splitter.max_tokens(8_192_u16 - 93).keep_first(1_usize);
+        let (outdated, recent) = splitter.split_counted(&messages);
+        assert_eq!(outdated, &messages[1..2]);
+        assert_eq!(recent, &messages[2..]);
+
+        let messages = conversation(4);
+        for splitter in [
+            ChatSplitter::new("gpt-4"),
+            ChatSplitter::new("gpt-4").max_input_tokens(30_u16),
+            ChatSplitter::new("gpt-4")
+                .max_input_tokens(30_u16)
+                .snap_to_turns(true),
+        ] {
+            let tokens = splitter.message_tokens(&messages).unwrap();
+            let counted: Vec<_> = messages
+                .iter()
+                .cloned()
+                .zip(tokens)
+                .map(|(message, tokens)| Cached(message, tokens))
+                .collect();
+            let (outdated, recent) = splitter.split(&messages);
+            let (counted_outdated, counted_recent) = splitter.split_counted(&counted);
+            assert_eq!(counted_outdated.len(), outdated.len());
+            assert_eq!(counted_recent.len(), recent.len());
+        }
+
+        let splitter = ChatSplitter::new("not-a-model");
+        assert!(matches!(
+            splitter.try_split_counted(&[Cached(user_message("Hi!"), 5)]),
+            Err(SplitError::UnknownModel(_))
+        ));
+    }
+
+    #[test]
+    fn function_call_arguments_are_counted() {
+        let function_call_message = |arguments: String| {
+            async_openai::types::ChatCompletionRequestAssistantMessageArgs::default()
+                .function_call(async_openai::types::FunctionCall {
+                    name: "get_current_weather".to_owned(),
+                    arguments,
+                })
+                .build()
+                .unwrap()
+                .into()
+        };
+        let small = function_call_message(r#"{"location": "Boston, MA"}"#.to_owned());
+        let large = function_call_message(format!(
+            r#"{{"locations": [{}]}}"#,
+            r#""Boston, MA", "#.repeat(64)
+        ));
+        let messages = [user_message("What's the weather like?"), small];
+
+        let splitter = ChatSplitter::new("gpt-4");
+        let tokens = splitter.count_tokens(&messages).unwrap();
+        assert!(tokens > splitter.count_tokens(&messages[..1]).unwrap() + 10);
+        let converted = into_tiktoken_rs_vec(messages.clone());
+        assert_eq!(
+            TiktokenTokenizer::new("gpt-4")
+                .count_message_tokens(&converted)
+                .unwrap()
+                .iter()
+                .sum::<usize>()
+                + 3,
+            tokens
+        );
+
+        let splitter = splitter.max_input_tokens(u32::try_from(tokens).unwrap());
+        assert_eq!(splitter.split_position(&messages), 0);
+        let messages = [large, messages[1].clone()];
+        assert_eq!(splitter.split_position(&messages), 1);
+    }
+
+    #[test]
+    fn keep_first_is_always_recent() {
+        let messages = conversation(4);
+
+        for max_tokens in [0_u16, 30, 60, 80] {
+            let splitter = ChatSplitter::default()
+                .with_tokenizer(TenTokensPerMessage)
+                .max_tokens(max_tokens)
+                .keep_first(2_usize);
+            let (head, _outdated, recent) = splitter.split_three(&messages);
+            assert_eq!(head, &messages[..2]);
+
+            // As many recent messages as fit after the kept ones
+            let fit = (100 - usize::from(max_tokens)) / 10;
+            assert_eq!(recent.len(), fit.saturating_sub(2).min(6));
+
+            let (_outdated, recent) = splitter.split_owned(messages.clone());
+            assert_eq!(&recent[..2], &messages[..2]);
+        }
+    }
+
+    #[test]
+    fn coalescing_reduces_tokens() {
+        let mut messages = vec![
+            user_message("Hi!"),
+            user_message("Are you there?"),
+            user_message("Hello?"),
+            assistant_message("Yes, I am here."),
+        ];
+        messages.extend(conversation(2));
+
+        let splitter = ChatSplitter::new("gpt-4");
+        let coalescing = splitter.clone().coalesce_consecutive(true);
+        let tokens = splitter.count_tokens(&messages).unwrap();
+        let coalesced_tokens = coalescing.count_tokens(&messages).unwrap();
+        assert!(coalesced_tokens < tokens);
+
+        let merged = [
+            user_message("Hi!\nAre you there?\nHello?"),
+            assistant_message("Yes, I am here."),
+        ];
+        assert_eq!(
+            coalescing.count_tokens(&messages[..4]).unwrap(),
+            splitter.count_tokens(&merged).unwrap()
+        );
+
+        // Message tokens add up to the same count.
+        let converted = into_tiktoken_rs_vec(messages.clone());
+        assert_eq!(
+            coalescing
+                .count_tiktoken_rs_message_tokens(&converted)
+                .unwrap()
+                .iter()
+                .sum::<usize>()
+                + 3,
+            coalesced_tokens
+        );
+
+        let coalescing = coalescing.max_input_tokens(u32::try_from(coalesced_tokens).unwrap());
+        let (outdated, recent) = coalescing.split(&messages);
+        assert!(outdated.is_empty());
+        assert_eq!(recent, &messages[..]);
+        assert_eq!(
+            CachingSplitter::new(coalescing.clone()).split(&messages),
+            coalescing.split(&messages)
+        );
+    }
+
+    #[test]
+    fn binding_constraint_tells_which_limit_split() {
+        let messages = conversation(4);
+
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(0_u16);
+        let binding_constraint =
+            |splitter: &ChatSplitter| splitter.split_with_stats(&messages).2.binding_constraint;
+        assert_eq!(binding_constraint(&splitter), BindingConstraint::None);
+
+        let splitter = splitter.max_messages(4_usize);
+        assert_eq!(binding_constraint(&splitter), BindingConstraint::Messages);
+
+        let splitter = splitter.max_messages(8_usize).max_tokens(70_u16);
+        assert_eq!(binding_constraint(&splitter), BindingConstraint::Tokens);
+
+        let splitter = splitter.max_messages(4_usize);
+        assert_eq!(binding_constraint(&splitter), BindingConstraint::Both);
+    }
+
+    #[test]
+    fn identical_splitters_are_equal() {
+        let hash = |splitter: &ChatSplitter| {
+            let mut hasher = DefaultHasher::new();
+            splitter.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        let splitter = ChatSplitter::new("gpt-4").max_messages(8_usize);
+        let other = ChatSplitter::new("gpt-4").max_messages(8_usize);
+        assert_eq!(splitter, other);
+        assert_eq!(hash(&splitter), hash(&other));
+        assert_ne!(splitter, other.max_messages(16_usize));
+
+        let splitter = splitter.with_tokenizer(TenTokensPerMessage);
+        assert_eq!(splitter, splitter.clone());
+        assert_eq!(hash(&splitter), hash(&splitter.clone()));
+        assert_ne!(
+            splitter,
+            ChatSplitter::new("gpt-4")
+                .max_messages(8_usize)
+                .with_tokenizer(TenTokensPerMessage)
+        );
+    }
+
+    #[test]
+    fn safety_margin_shrinks_recent() {
+        let messages = conversation(4);
+
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(50_u16);
+        assert_eq!(splitter.split_position(&messages), 3);
+
+        let splitter = splitter.safety_margin_tokens(1_u16);
+        assert_eq!(splitter.split_position(&messages), 4);
+
+        let splitter = splitter.safety_margin_tokens(20_u16);
+        assert_eq!(splitter.split_position(&messages), 5);
+    }
+
+    #[test]
+    #[cfg(feature = "tokio")]
+    fn split_async_matches_split() {
+        use std::rc::Rc;
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        for (splitter, messages) in fixtures() {
+            assert_eq!(
+                runtime.block_on(splitter.split_async(&messages)),
+                splitter.split(&messages)
+            );
+        }
+
+        // Messages need not be sent to another thread.
+        #[derive(Clone, Debug, PartialEq)]
+        struct Shared(Rc<tiktoken_rs::ChatCompletionRequestMessage>);
+
+        impl IntoChatCompletionRequestMessage for Shared {
+            fn into_tiktoken_rs(self) -> tiktoken_rs::ChatCompletionRequestMessage {
+                (*self.0).clone()
+            }
+        }
+
+        let messages: Vec<_> = conversation(4)
+            .into_iter()
+            .map(|message| Shared(Rc::new(message.into_tiktoken_rs())))
+            .collect();
+        let splitter = ChatSplitter::new("gpt-4")
+            .max_input_tokens(40_u16)
+            .keep_first(1_usize);
+        assert_eq!(
+            runtime.block_on(splitter.split_async(&messages)),
+            splitter.split(&messages)
+        );
+    }
+
+    #[test]
+    fn split_batch_matches_split_owned() {
+        let (splitters, conversations): (Vec<_>, Vec<_>) = fixtures().into_iter().unzip();
+
+        for splitter in splitters {
+            let splits = splitter.split_batch(&conversations);
+            assert_eq!(splits.len(), conversations.len());
+            for (split, messages) in splits.into_iter().zip(&conversations) {
+                assert_eq!(split, splitter.split_owned(messages.clone()));
+            }
+        }
+    }
+
+    #[test]
+    fn fitting_message_count_ignores_max_messages() {
+        let messages = conversation(4);
+
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(50_u16)
+            .max_messages(2_usize);
+        assert_eq!(splitter.fitting_message_count(&messages), 5);
+        assert_eq!(splitter.fitting_message_count(&messages[..3]), 3);
+        assert_eq!(splitter.split(&messages).1.len(), 2);
+    }
+
+    #[test]
+    fn split_retaining_drops_messages_first() {
+        let messages = vec![
+            user_message("What's the weather?"),
+            tool_message("Sunny", "call_0"),
+            assistant_message("It's sunny."),
+            user_message("And tomorrow?"),
+            tool_message("Rainy", "call_1"),
+            assistant_message("It'll rain."),
+        ];
+        let is_not_tool = |message: &async_openai::types::ChatCompletionRequestMessage| {
+            !matches!(
+                message,
+                async_openai::types::ChatCompletionRequestMessage::Tool(_)
+            )
+        };
+
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(70_u16);
+        let (outdated, recent) = splitter.split_retaining(&messages, is_not_tool);
+        assert_eq!(recent, [&messages[2..4], &messages[5..]].concat());
+        assert_eq!(outdated, [&messages[..2], &messages[4..5]].concat());
+    }
+
+    #[test]
+    fn message_separator_tokens_trim_more() {
+        let messages = conversation(5);
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(40_u16);
+        assert_eq!(splitter.count_tokens(&messages).unwrap(), 100);
+        assert_eq!(splitter.split(&messages).1.len(), 6);
+
+        let splitter = splitter.message_separator_tokens(2_usize);
+        assert_eq!(splitter.count_tokens(&messages).unwrap(), 118);
+        assert_eq!(splitter.split(&messages).1.len(), 5);
+        assert_ne!(splitter, splitter.clone().message_separator_tokens(0_usize));
+
+        let mut incremental = IncrementalSplitter::new(splitter.clone());
+        for message in &messages {
+            incremental.push(message.clone());
+        }
+        assert_eq!(incremental.recent(), splitter.split(&messages).1);
+    }
+
+    #[test]
+    fn recent_async_openai_includes_pinned_messages() {
+        let mut messages = vec![system_message("You are a helpful assistant.")];
+        messages.extend(conversation(5));
+
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(50_u16)
+            .pin_system_prompt(true);
+        let recent = splitter.recent_async_openai(&messages);
+        assert_eq!(recent, splitter.split_owned(messages.clone()).1);
+        assert_eq!(recent.len(), 5);
+        assert_eq!(recent[0], messages[0]);
+    }
+
+    #[test]
+    fn split_with_system_dedupes_system_prompt() {
+        let system: async_openai::types::ChatCompletionRequestMessage =
+            system_message("You are a helpful assistant.");
+        let mut messages = vec![system.clone()];
+        messages.extend(conversation(1));
+
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(50_u16);
+        let (outdated, recent) = splitter.split_with_system(system.clone(), &messages);
+        assert!(outdated.is_empty());
+        assert_eq!(recent, [slice::from_ref(&system), &messages[..]].concat());
+
+        let splitter = splitter.dedupe_system(true);
+        let (outdated, recent) = splitter.split_with_system(system.clone(), &messages);
+        assert_eq!(outdated, slice::from_ref(&system));
+        assert_eq!(recent, [slice::from_ref(&system), &messages[1..]].concat());
+
+        // The system prompt counts towards every message limit.
+        let messages = conversation(4);
+        for splitter in [
+            splitter.clone().max_messages(3_usize),
+            splitter.clone().message_limit(3_usize),
+            splitter
+                .clone()
+                .max_messages(2_usize)
+                .soft_limits(SoftLimits {
+                    message_slack: 1,
+                    token_slack: 0,
+                }),
+            splitter
+                .clone()
+                .strategy(SplitStrategy::TokensOnly)
+                .message_limit(3_usize),
+        ] {
+            let (outdated, recent) = splitter.split_with_system(system.clone(), &messages);
+            assert_eq!(outdated, &messages[..6]);
+            assert_eq!(recent, [slice::from_ref(&system), &messages[6..]].concat());
+        }
+
+        // So does any prefix reserved before.
+        let splitter = splitter
+            .max_messages(4_usize)
+            .reserve_prefix(&messages[..1]);
+        let (outdated, recent) = splitter.split_with_system(system.clone(), &messages);
+        assert_eq!(outdated, &messages[..6]);
+        assert_eq!(recent, [slice::from_ref(&system), &messages[6..]].concat());
+    }
+
+    #[test]
+    fn max_messages_boundaries() {
+        let messages = conversation(MAX_MESSAGES_LIMIT);
+        let splitter = ChatSplitter::default().strategy(SplitStrategy::MessagesOnly);
+
+        for (max_messages, recent) in [
+            (0, 0),
+            (1, 1),
+            (messages.len(), MAX_MESSAGES_LIMIT),
+            (messages.len() + 1, MAX_MESSAGES_LIMIT),
+            (MAX_MESSAGES_LIMIT + 1, MAX_MESSAGES_LIMIT),
+            (usize::MAX, MAX_MESSAGES_LIMIT),
+        ] {
+            let (outdated, recent_messages) =
+                splitter.clone().max_messages(max_messages).split(&messages);
+            assert_eq!(
+                recent_messages.len(),
+                recent,
+                "max_messages = {max_messages}"
+            );
+            assert_eq!(outdated.len() + recent_messages.len(), messages.len());
+        }
+
+        let splitter = splitter.message_limit(usize::MAX);
+        for (max_messages, recent) in [
+            (0, 0),
+            (messages.len(), messages.len()),
+            (messages.len() + 1, messages.len()),
+            (usize::MAX, messages.len()),
+        ] {
+            let recent_messages = splitter
+                .clone()
+                .max_messages(max_messages)
+                .split(&messages)
+                .1;
+            assert_eq!(
+                recent_messages.len(),
+                recent,
+                "max_messages = {max_messages}"
+            );
+        }
+        assert!(splitter
+            .max_messages(0_usize)
+            .split(&[] as &[SimpleMessage])
+            .1
+            .is_empty());
+    }
+
+    #[test]
+    fn combined_ceiling_bounds_input_tokens() {
+        let messages = conversation(5);
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(20_u16);
+        assert_eq!(splitter.split(&messages).1.len(), 8);
+
+        let splitter = splitter.combined_ceiling(60_usize);
+        assert_eq!(splitter.split(&messages).1.len(), 4);
+        let splitter = splitter.combined_ceiling(59_usize);
+        assert_eq!(splitter.split(&messages).1.len(), 3);
+        let splitter = splitter.combined_ceiling(1_000_usize);
+        assert_eq!(splitter.split(&messages).1.len(), 8);
+
+        let splitter = splitter.combined_ceiling(20_usize);
+        assert_eq!(
+            splitter.try_split(&messages),
+            Err(SplitError::CompletionBudgetTooLarge {
+                max_tokens: 20,
+                context_size: 20,
+            })
+        );
+    }
+
+    #[test]
+    fn empty_recent_is_reported_by_plan() {
+        let messages = conversation(2);
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(95_u16);
+        let (outdated, recent) = splitter.split(&messages);
+        assert_eq!(outdated, messages);
+        assert!(recent.is_empty());
+        let plan = splitter.plan(&messages);
+        assert_eq!(plan.recent_messages, 0);
+        assert_eq!(plan.binding_constraint(), BindingConstraint::Tokens);
+
+        let splitter = splitter.max_tokens(50_u16).max_messages(0_usize);
+        assert!(splitter.split(&messages).1.is_empty());
+        let plan = splitter.plan(&messages);
+        assert_eq!(plan.recent_messages, 0);
+        assert_eq!(plan.binding_constraint(), BindingConstraint::Messages);
+    }
+
+    #[test]
+    fn with_ratio_sets_max_tokens() {
+        assert_eq!(
+            ChatSplitter::with_ratio("gpt-4", 0.5),
+            ChatSplitter::new("gpt-4")
+        );
+        assert_eq!(ChatSplitter::with_ratio("gpt-4", 0.25).max_tokens, 2_048);
+        assert_eq!(ChatSplitter::with_ratio("gpt-4", 0.75).max_tokens, 6_144);
+
+        let splitter = ChatSplitter::with_ratio("gpt-4", 1.0);
+        assert_eq!(splitter.max_tokens, 8_192);
+        assert!(matches!(
+            splitter.try_split(&conversation(1)),
+            Err(SplitError::CompletionBudgetTooLarge { .. })
+        ));
+        assert_eq!(ChatSplitter::with_ratio("gpt-4", -1.0).max_tokens, 0);
+    }
+
+    #[test]
+    fn on_probe_observes_the_search() {
+        let probes = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(50_u16)
+            .on_probe({
+                let probes = Arc::clone(&probes);
+                move |start, tokens| probes.lock().unwrap().push((start, tokens))
+            });
+
+        let messages = conversation(5);
+        assert_eq!(splitter.split(&messages).1, &messages[5..]);
+        let probes = probes.lock().unwrap();
+        assert_eq!(*probes, [(0, 100), (5, 50), (2, 80), (3, 70), (4, 60)]);
+    }
+
+    #[test]
+    fn soft_limits_allow_slack() {
+        let messages = conversation(6);
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(50_u16)
+            .max_messages(4_usize);
+        assert_eq!(splitter.split(&messages).1.len(), 4);
+
+        let soft_limits = SoftLimits {
+            message_slack: 2,
+            token_slack: 10,
+        };
+        let splitter = splitter.soft_limits(soft_limits);
+        assert_eq!(splitter.split(&messages).1.len(), 6);
+        assert_eq!(
+            splitter
+                .clone()
+                .message_limit(5_usize)
+                .split(&messages)
+                .1
+                .len(),
+            5
+        );
+
+        let splitter = splitter.max_messages(100_usize);
+        assert_eq!(splitter.split(&messages).1.len(), 6);
+
+        // The context size is never exceeded.
+        let splitter = splitter.soft_limits(SoftLimits {
+            token_slack: 1_000,
+            ..soft_limits
+        });
+        assert_eq!(splitter.split(&messages).1.len(), 10);
+    }
+
+    #[test]
+    fn outdated_and_recent_async_openai_reconstruct_chat() {
+        let messages: Vec<_> = conversation(5)
+            .into_iter()
+            .map(|message| message.into_tiktoken_rs())
+            .collect();
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(50_u16);
+
+        let outdated = splitter.outdated_async_openai(&messages);
+        let recent = splitter.recent_async_openai(&messages);
+        assert_eq!(outdated.len(), 5);
+        assert_eq!(
+            [outdated, recent].concat(),
+            into_async_openai_vec(messages.iter().cloned())
+        );
+    }
+
+    #[test]
+    fn bpe_is_built_once_per_model() {
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+        let messages = conversation(2);
+        let splitter = ChatSplitter::new("gpt-4");
+        assert_send_sync(&splitter);
+        assert!(splitter.bpe.0.get().is_none());
+
+        let tokens = splitter.count_tokens(&messages).unwrap();
+        assert!(splitter.bpe.0.get().is_some());
+        assert_eq!(
+            tokens,
+            TiktokenTokenizer::new("gpt-4")
+                .count_tokens(&into_tiktoken_rs_vec(messages.clone()))
+                .unwrap()
+        );
+        let clone = splitter.clone();
+        assert!(Arc::ptr_eq(&clone.bpe.0, &splitter.bpe.0));
+        assert_eq!(clone, splitter);
+
+        let splitter = splitter.model("gpt-3.5-turbo");
+        assert!(splitter.bpe.0.get().is_none());
+        assert_eq!(
+            splitter.count_tokens(&messages).unwrap(),
+            TiktokenTokenizer::new("gpt-3.5-turbo")
+                .count_tokens(&into_tiktoken_rs_vec(messages))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn split_with_floor_index_keeps_messages_after_floor() {
+        let messages = conversation(5);
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(50_u16);
+        assert_eq!(splitter.split(&messages).1, &messages[5..]);
+
+        for floor in [5, 8, 10, 100] {
+            assert_eq!(
+                splitter.split_with_floor_index(&messages, floor),
+                splitter.split(&messages)
+            );
+        }
+        assert_eq!(
+            splitter.split_with_floor_index(&messages, 2),
+            (&messages[..2], &messages[2..])
+        );
+
+        let splitter = splitter.pin_system_prompt(true).keep_first(1_usize);
+        assert_eq!(
+            splitter.split_with_floor_index(&messages, 0),
+            (&messages[1..1], &messages[1..])
+        );
+    }
+
+    #[test]
+    fn developer_messages_are_system_prompts() {
+        let developer = SimpleMessage::new("developer", "Answer in French.");
+        assert_eq!(
+            parse_role("developer"),
+            Ok(async_openai::types::Role::System)
+        );
+        assert_eq!(
+            developer.clone().into_async_openai(),
+            system_message("Answer in French.")
+        );
+        assert_eq!(developer.clone().into_tiktoken_rs().role, "developer");
+
+        let mut messages = vec![developer];
+        messages.extend((0..5).flat_map(|_| {
+            [
+                SimpleMessage::new("user", "Hi!"),
+                SimpleMessage::new("assistant", "Hello!"),
+            ]
+        }));
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(50_u16)
+            .pin_system_prompt(true);
+        let (head, outdated, recent) = splitter.split_three(&messages);
+        assert_eq!(head, &messages[..1]);
+        assert_eq!(outdated, &messages[1..7]);
+        assert_eq!(recent, &messages[7..]);
+    }
+
+    #[test]
+    fn hysteresis_tokens_stabilize_windows() {
+        let messages = conversation(5);
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(50_u16)
+            .hysteresis_tokens(10_usize);
+        assert_eq!(splitter.split(&messages[..6]).1.len(), 6);
+        assert_eq!(splitter.split(&messages[..7]).1.len(), 4);
+
+        let mut incremental = IncrementalSplitter::new(splitter);
+        let mut recent_lens = Vec::new();
+        for message in messages {
+            incremental.push(message);
+            recent_lens.push(incremental.recent().len());
+        }
+        assert_eq!(recent_lens, [1, 2, 3, 4, 5, 6, 4, 5, 6, 4]);
+    }
+
+    #[test]
+    fn recent_cow_borrows_whenever_possible() {
+        let mut messages = vec![system_message("You are a helpful assistant.")];
+        messages.extend(conversation(4));
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(50_u16)
+            .pin_system_prompt(true);
+
+        // Everything fits,
+        // so 'recent' points into the chat itself.
+        let Cow::Borrowed(recent) = splitter.recent_cow(&messages[..5]) else {
+            panic!("expected borrowed messages");
+        };
+        assert!(std::ptr::eq(recent, &messages[..5]));
+
+        // Only pinned messages in front of 'outdated' ones are cloned.
+        let recent = splitter.recent_cow(&messages);
+        assert!(matches!(&recent, Cow::Owned(_)));
+        assert_eq!(
+            recent.into_owned(),
+            splitter.split_owned(messages.clone()).1
+        );
+
+        let splitter = splitter.pin_system_prompt(false);
+        let Cow::Borrowed(recent) = splitter.recent_cow(&messages) else {
+            panic!("expected borrowed messages");
+        };
+        assert!(std::ptr::eq(recent, splitter.split(&messages).1));
+    }
+
+    #[test]
+    fn reserve_prefix_accounts_for_prepended_messages() {
+        let system: async_openai::types::ChatCompletionRequestMessage =
+            system_message("You are a helpful assistant.");
+        let stored_messages = conversation(5);
+
+        for (max_tokens, max_messages, expected) in
+            [(50_u16, 100_usize, 5), (0, 4, 4), (0, 100, 10)]
+        {
+            let splitter = ChatSplitter::default()
+                .with_tokenizer(TenTokensPerMessage)
+                .max_tokens(max_tokens)
+                .max_messages(max_messages)
+                .reserve_prefix(slice::from_ref(&system));
+            let (_outdated, recent) = splitter.split(&stored_messages);
+
+            let mut messages = vec![system.clone()];
+            messages.extend(recent.iter().cloned());
+            assert_eq!(messages.len(), expected);
+            assert!(messages.len() <= max_messages);
+            assert!(splitter.count_tokens(&messages).unwrap() + max_tokens as usize <= 100);
+        }
+    }
+
+    #[test]
+    fn references_to_non_clone_messages_can_be_split() {
+        struct Stored(String);
+
+        impl ToChatCompletionRequestMessage for Stored {
+            fn to_tiktoken_rs(
+                &self,
+            ) -> Result<tiktoken_rs::ChatCompletionRequestMessage, ConversionError> {
+                Ok(tiktoken_rs::ChatCompletionRequestMessage {
+                    role: "user".to_owned(),
+                    content: Some(self.0.clone()),
+                    ..Default::default()
+                })
+            }
+
+            fn to_async_openai(
+                &self,
+            ) -> Result<async_openai::types::ChatCompletionRequestMessage, ConversionError>
+            {
+                Ok(user_message(&self.0))
+            }
+
+            fn count_images(&self) -> usize {
+                0
+            }
+        }
+
+        let arena: Vec<_> = (0..10).map(|i| Stored(format!("Message {i}"))).collect();
+        let messages: Vec<&Stored> = arena.iter().step_by(2).collect();
+
+        let splitter = ChatSplitter::default()
+            .with_tokenizer(TenTokensPerMessage)
+            .max_tokens(70_u16);
+        let (outdated, recent) = splitter.split_refs(&messages);
+        assert_eq!((outdated.len(), recent.len()), (2, 3));
+        assert_eq!(
+            recent.iter().map(|message| &*message.0).collect::<Vec<_>>(),
+            ["Message 4", "Message 6", "Message 8"]
+        );
+        assert_eq!(splitter.split_position(&messages), outdated.len());
     }
 }
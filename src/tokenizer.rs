@@ -0,0 +1,575 @@
+//! Token counting backends and model information.
+
+use std::fmt;
+use std::hash::Hash;
+use std::slice;
+
+use tiktoken_rs::get_bpe_from_model;
+use tiktoken_rs::model::get_context_size;
+use tiktoken_rs::num_tokens_from_messages;
+use tiktoken_rs::CoreBPE;
+
+use crate::SplitError;
+
+/// Token counting backend for [`ChatSplitter`](crate::ChatSplitter).
+///
+/// Implement this to use a tokenizer not provided by [`tiktoken_rs`],
+/// e.g.,
+/// for a local model,
+/// and pass it to [`ChatSplitter::with_tokenizer`](crate::ChatSplitter::with_tokenizer).
+pub trait Tokenizer: fmt::Debug + Send + Sync {
+    /// Count the input tokens the given messages take,
+    /// including any per-message and per-reply overhead.
+    ///
+    /// # Errors
+    ///
+    /// If the messages cannot be tokenized.
+    fn count_tokens(
+        &self,
+        messages: &[tiktoken_rs::ChatCompletionRequestMessage],
+    ) -> Result<usize, SplitError>;
+
+    /// Count the tokens each of the given messages take,
+    /// excluding the per-reply overhead.
+    ///
+    /// Token counts are assumed to be additive,
+    /// i.e.,
+    /// counting a chat gives the per-reply overhead
+    /// (the count of an empty chat)
+    /// plus the counts of each message.
+    /// The default implementation relies on this and counts each message
+    /// separately,
+    /// but implementors are encouraged to provide a faster one.
+    ///
+    /// # Errors
+    ///
+    /// If the messages cannot be tokenized.
+    #[inline]
+    fn count_message_tokens(
+        &self,
+        messages: &[tiktoken_rs::ChatCompletionRequestMessage],
+    ) -> Result<Vec<usize>, SplitError> {
+        let reply_tokens = self.count_tokens(&[])?;
+        messages
+            .iter()
+            .map(|message| {
+                Ok(self
+                    .count_tokens(slice::from_ref(message))?
+                    .saturating_sub(reply_tokens))
+            })
+            .collect()
+    }
+
+    /// Get the context size,
+    /// i.e.,
+    /// the maximum number of input and generated tokens together.
+    fn context_size(&self) -> usize;
+}
+
+/// Default [`Tokenizer`] based on [`tiktoken_rs`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TiktokenTokenizer {
+    /// The model to use for tokenization,
+    /// e.g.,
+    /// `gpt-3.5-turbo`.
+    model: String,
+
+    /// The per-message and per-reply token overhead,
+    /// if not using the one of [`tiktoken_rs`].
+    overhead: Option<(usize, usize)>,
+}
+
+impl TiktokenTokenizer {
+    /// Create a new [`TiktokenTokenizer`] for the given model.
+    #[inline]
+    pub fn new(model: impl Into<String>) -> Self {
+        Self {
+            model: model.into(),
+            overhead: None,
+        }
+    }
+
+    /// Set the per-message and per-reply token overhead,
+    /// i.e.,
+    /// the tokens added to every message besides its role,
+    /// content and name,
+    /// and
+    /// the tokens every reply is primed with.
+    ///
+    /// Defaults to the overhead used by [`tiktoken_rs`],
+    /// which is four tokens per message for `gpt-3.5` models
+    /// and
+    /// three otherwise,
+    /// plus three tokens per reply.
+    #[inline]
+    #[must_use]
+    pub fn with_overhead(mut self, per_message: usize, per_reply: usize) -> Self {
+        self.overhead = Some((per_message, per_reply));
+        self
+    }
+
+    /// Get the per-message and per-reply token overhead.
+    #[inline]
+    pub(crate) fn overhead(&self) -> (usize, usize) {
+        self.overhead.unwrap_or_else(|| {
+            if self.model.starts_with("gpt-3.5") {
+                (4, 3)
+            } else {
+                (3, 3)
+            }
+        })
+    }
+}
+
+impl Tokenizer for TiktokenTokenizer {
+    #[inline]
+    fn count_tokens(
+        &self,
+        messages: &[tiktoken_rs::ChatCompletionRequestMessage],
+    ) -> Result<usize, SplitError> {
+        // `num_tokens_from_messages` ignores function calls.
+        let has_function_calls = messages
+            .iter()
+            .any(|message| message.function_call.is_some());
+        match self.overhead {
+            None if !has_function_calls => num_tokens_from_messages(&self.model, messages)
+                .map_err(|_| SplitError::UnknownModel(self.model.clone())),
+            _ => Ok(self
+                .count_message_tokens(messages)?
+                .into_iter()
+                .sum::<usize>()
+                + self.overhead().1),
+        }
+    }
+
+    /// Count the tokens each of the given messages take,
+    /// excluding the per-reply overhead.
+    ///
+    /// This follows the same rules as [`num_tokens_from_messages`],
+    /// but builds the tokenizer only once,
+    /// and
+    /// also counts the name and arguments of function calls.
+    #[inline]
+    fn count_message_tokens(
+        &self,
+        messages: &[tiktoken_rs::ChatCompletionRequestMessage],
+    ) -> Result<Vec<usize>, SplitError> {
+        if !is_chat_model(&self.model) {
+            return Err(SplitError::UnknownModel(self.model.clone()));
+        }
+        let bpe = get_bpe_from_model(&self.model)
+            .map_err(|_| SplitError::UnknownModel(self.model.clone()))?;
+        Ok(self.count_message_tokens_with(&bpe, messages))
+    }
+
+    #[inline]
+    fn context_size(&self) -> usize {
+        context_size(&self.model)
+    }
+}
+
+impl TiktokenTokenizer {
+    /// Count the tokens each of the given messages take,
+    /// excluding the per-reply overhead,
+    /// using an already built tokenizer of the model.
+    #[inline]
+    fn count_message_tokens_with(
+        &self,
+        bpe: &CoreBPE,
+        messages: &[tiktoken_rs::ChatCompletionRequestMessage],
+    ) -> Vec<usize> {
+        let tokens_per_message = self.overhead().0 as isize;
+        let tokens_per_name = if self.model.starts_with("gpt-3.5") {
+            // Every message follows `<im_start>{role/name}\n{content}<im_end>\n`,
+            // and if there's a name, the role is omitted.
+            -1
+        } else {
+            1
+        };
+
+        let count = |text: &str| bpe.encode_with_special_tokens(text).len() as isize;
+        messages
+            .iter()
+            .map(|message| {
+                let mut tokens = tokens_per_message;
+                tokens += count(&message.role);
+                tokens += count(message.content.as_deref().unwrap_or_default());
+                if let Some(name) = &message.name {
+                    tokens += count(name) + tokens_per_name;
+                }
+                if let Some(function_call) = &message.function_call {
+                    tokens += count(&function_call.name) + count(&function_call.arguments);
+                }
+                usize::try_from(tokens).unwrap_or_default()
+            })
+            .collect()
+    }
+}
+
+/// [`TiktokenTokenizer`] with its tokenizer already built,
+/// as used by [`ChatSplitter`](crate::ChatSplitter) to avoid building it for every split.
+pub(crate) struct CachedTiktokenTokenizer<'a> {
+    /// The tokenizer giving the counting rules.
+    pub(crate) tokenizer: TiktokenTokenizer,
+
+    /// The already built tokenizer of the model.
+    pub(crate) bpe: &'a CoreBPE,
+}
+
+impl fmt::Debug for CachedTiktokenTokenizer<'_> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CachedTiktokenTokenizer")
+            .field("tokenizer", &self.tokenizer)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Tokenizer for CachedTiktokenTokenizer<'_> {
+    /// Count the tokens the given messages take,
+    /// which is the same as [`num_tokens_from_messages`],
+    /// but also counts function calls.
+    #[inline]
+    fn count_tokens(
+        &self,
+        messages: &[tiktoken_rs::ChatCompletionRequestMessage],
+    ) -> Result<usize, SplitError> {
+        Ok(self
+            .count_message_tokens(messages)?
+            .into_iter()
+            .sum::<usize>()
+            + self.tokenizer.overhead().1)
+    }
+
+    #[inline]
+    fn count_message_tokens(
+        &self,
+        messages: &[tiktoken_rs::ChatCompletionRequestMessage],
+    ) -> Result<Vec<usize>, SplitError> {
+        Ok(self.tokenizer.count_message_tokens_with(self.bpe, messages))
+    }
+
+    #[inline]
+    fn context_size(&self) -> usize {
+        self.tokenizer.context_size()
+    }
+}
+
+/// Roughly estimate the tokens a message takes,
+/// without any tokenization.
+///
+/// This assumes about four characters per token,
+/// plus a small per-message overhead,
+/// and is meant to be used with [`ChatSplitter::with_estimator`](crate::ChatSplitter::with_estimator).
+#[inline]
+pub fn estimate_tokens(message: &tiktoken_rs::ChatCompletionRequestMessage) -> usize {
+    let chars = message.role.chars().count()
+        + message
+            .content
+            .as_deref()
+            .map_or(0, |content| content.chars().count())
+        + message
+            .name
+            .as_deref()
+            .map_or(0, |name| name.chars().count())
+        + message.function_call.as_ref().map_or(0, |function_call| {
+            function_call.name.chars().count() + function_call.arguments.chars().count()
+        });
+    (chars + 3) / 4 + 4
+}
+
+/// Truncate the given content to at most `max_chars` characters,
+/// e.g.,
+/// to preview 'outdated' messages.
+///
+/// This never splits a character,
+/// so it is safe for multibyte content.
+#[inline]
+pub fn truncate_content_chars(content: &str, max_chars: usize) -> &str {
+    content
+        .char_indices()
+        .nth(max_chars)
+        .map_or(content, |(end, _)| &content[..end])
+}
+
+/// [`Tokenizer`] based on an approximate token estimator,
+/// as set by [`ChatSplitter::with_estimator`](crate::ChatSplitter::with_estimator).
+pub(crate) struct Estimator<F> {
+    /// The estimator giving the tokens each message takes.
+    pub(crate) estimator: F,
+}
+
+impl<F> fmt::Debug for Estimator<F> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Estimator").finish_non_exhaustive()
+    }
+}
+
+impl<F> Tokenizer for Estimator<F>
+where
+    F: Fn(&tiktoken_rs::ChatCompletionRequestMessage) -> usize + Send + Sync,
+{
+    #[inline]
+    fn count_tokens(
+        &self,
+        messages: &[tiktoken_rs::ChatCompletionRequestMessage],
+    ) -> Result<usize, SplitError> {
+        Ok(messages.iter().map(&self.estimator).sum())
+    }
+
+    #[inline]
+    fn count_message_tokens(
+        &self,
+        messages: &[tiktoken_rs::ChatCompletionRequestMessage],
+    ) -> Result<Vec<usize>, SplitError> {
+        Ok(messages.iter().map(&self.estimator).collect())
+    }
+
+    #[inline]
+    fn context_size(&self) -> usize {
+        // The context size of the model is used instead
+        // (see `ChatSplitter::context_size`).
+        DEFAULT_CONTEXT_SIZE
+    }
+}
+
+/// Check whether [`tiktoken_rs`] supports token counting for the given chat
+/// model.
+#[inline]
+pub(crate) fn is_chat_model(model: &str) -> bool {
+    matches!(
+        tiktoken_rs::tokenizer::get_tokenizer(model),
+        Some(
+            tiktoken_rs::tokenizer::Tokenizer::Cl100kBase
+                | tiktoken_rs::tokenizer::Tokenizer::O200kBase
+        )
+    )
+}
+
+/// Check whether the given model is supported,
+/// i.e.,
+/// whether the default tokenizer can count tokens for it.
+///
+/// This is useful to validate configuration early,
+/// since splitting with an unsupported model fails
+/// ([`SplitError::UnknownModel`]),
+/// unless a custom tokenizer is used
+/// (see [`ChatSplitter::with_tokenizer`](crate::ChatSplitter::with_tokenizer)).
+#[inline]
+pub fn is_supported_model(model: &str) -> bool {
+    is_chat_model(model)
+}
+
+/// Get the known supported models.
+///
+/// This list is not exhaustive:
+/// e.g.,
+/// dated snapshots of these models are supported too
+/// (see [`is_supported_model`]).
+#[inline]
+pub fn supported_models() -> &'static [&'static str] {
+    SUPPORTED_MODELS
+}
+
+/// Known models supported by the default tokenizer.
+const SUPPORTED_MODELS: &[&str] = &[
+    "gpt-4o",
+    "gpt-4o-mini",
+    "gpt-4-turbo",
+    "gpt-4-32k",
+    "gpt-4",
+    "gpt-3.5-turbo",
+];
+
+/// A model supported by the default tokenizer
+/// (see [`is_supported_model`]).
+///
+/// It converts into a [`String`],
+/// so it can be given to [`ChatSplitter::new`](crate::ChatSplitter::new) and [`ChatSplitter::model`](crate::ChatSplitter::model),
+/// which then never fail because of an unknown model.
+///
+/// # Examples
+///
+/// ```
+/// use chat_splitter::ChatSplitter;
+/// use chat_splitter::Model;
+///
+/// let model = Model::try_from("gpt-4")?;
+/// let splitter = ChatSplitter::new(model);
+///
+/// assert!(Model::try_from("gtp-4").is_err());
+/// # Ok::<(), chat_splitter::SplitError>(())
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Model(String);
+
+impl Model {
+    /// Get the name of the model.
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<&str> for Model {
+    type Error = SplitError;
+
+    #[inline]
+    fn try_from(model: &str) -> Result<Self, Self::Error> {
+        Self::try_from(model.to_owned())
+    }
+}
+
+impl TryFrom<String> for Model {
+    type Error = SplitError;
+
+    #[inline]
+    fn try_from(model: String) -> Result<Self, Self::Error> {
+        if is_chat_model(&model) {
+            Ok(Self(model))
+        } else {
+            Err(SplitError::UnknownModel(model))
+        }
+    }
+}
+
+impl AsRef<str> for Model {
+    #[inline]
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl fmt::Display for Model {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<Model> for String {
+    #[inline]
+    fn from(model: Model) -> Self {
+        model.0
+    }
+}
+
+/// Context sizes of models that [`tiktoken_rs`] might not know about yet,
+/// or
+/// for which it gives a stale value.
+///
+/// Entries are matched by prefix,
+/// in order,
+/// so more specific prefixes must come first.
+const CONTEXT_SIZES: &[(&str, usize)] = &[
+    ("gpt-4o-mini", 128_000),
+    ("gpt-4o", 128_000),
+    ("gpt-4-turbo", 128_000),
+    ("gpt-3.5-turbo-instruct", 4_096),
+];
+
+/// Context size assumed when the one of a model is implausibly small.
+pub(crate) const DEFAULT_CONTEXT_SIZE: usize = 4_096;
+
+/// Smallest context size considered plausible for a chat model.
+const MIN_PLAUSIBLE_CONTEXT_SIZE: usize = 1_024;
+
+/// Get the context size of the given model.
+///
+/// Known models are looked up in [`CONTEXT_SIZES`] first,
+/// falling back to [`tiktoken_rs`].
+/// Implausibly small context sizes are replaced
+/// (see [`plausible_context_size`]).
+#[inline]
+pub(crate) fn context_size(model: &str) -> usize {
+    plausible_context_size(reported_context_size(model))
+}
+
+/// Get the context size of the given model as reported,
+/// even if implausibly small.
+#[inline]
+fn reported_context_size(model: &str) -> usize {
+    CONTEXT_SIZES
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map_or_else(
+            || get_context_size(model),
+            |&(_, context_size)| context_size,
+        )
+}
+
+/// Guard against implausibly small context sizes,
+/// e.g.,
+/// zero,
+/// falling back to [`DEFAULT_CONTEXT_SIZE`].
+#[inline]
+pub(crate) fn plausible_context_size(context_size: usize) -> usize {
+    if context_size < MIN_PLAUSIBLE_CONTEXT_SIZE {
+        return DEFAULT_CONTEXT_SIZE;
+    }
+    context_size
+}
+
+/// Log a warning if the context size of the given model is implausibly
+/// small.
+///
+/// This is done once,
+/// when the model is set,
+/// rather than on every lookup.
+#[inline]
+pub(crate) fn warn_implausible_context_size(model: &str) {
+    let context_size = reported_context_size(model);
+    if context_size < MIN_PLAUSIBLE_CONTEXT_SIZE {
+        log::warn!(
+            "context size of '{model}' = {context_size} < {MIN_PLAUSIBLE_CONTEXT_SIZE}, using {DEFAULT_CONTEXT_SIZE}"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ChatSplitter;
+
+    #[test]
+    fn models_are_validated() {
+        for model in supported_models() {
+            let model = Model::try_from(*model).unwrap();
+            assert_eq!(ChatSplitter::new(model.clone()).model, model.as_str());
+        }
+        assert_eq!(
+            Model::try_from("gpt-4-0613").unwrap().to_string(),
+            "gpt-4-0613"
+        );
+        assert_eq!(
+            Model::try_from("gtp-4"),
+            Err(SplitError::UnknownModel("gtp-4".to_owned()))
+        );
+        assert_eq!(
+            Model::try_from(String::from("text-davinci-003")),
+            Err(SplitError::UnknownModel("text-davinci-003".to_owned()))
+        );
+    }
+
+    #[test]
+    fn supported_models_are_supported() {
+        for model in supported_models() {
+            assert!(is_supported_model(model), "{model}");
+        }
+        assert!(is_supported_model("gpt-4-0613"));
+        assert!(!is_supported_model("gtp-4"));
+        assert!(!is_supported_model("text-davinci-003"));
+    }
+
+    #[test]
+    fn truncate_content_chars_respects_char_boundaries() {
+        assert_eq!(truncate_content_chars("Hello, world!", 5), "Hello");
+        assert_eq!(truncate_content_chars("Hello", 5), "Hello");
+        assert_eq!(truncate_content_chars("Hello", 10), "Hello");
+        assert_eq!(truncate_content_chars("Hello", 0), "");
+        assert_eq!(truncate_content_chars("🦀🦀🦀", 2), "🦀🦀");
+        assert_eq!(truncate_content_chars("你好，世界", 2), "你好");
+        assert_eq!(truncate_content_chars("", 3), "");
+    }
+}